@@ -0,0 +1,21 @@
+import math
+from collections import Counter
+
+# Counts how often every word occurs.
+def word_count(text):
+    counts = Counter()
+    for word in text.split():
+        counts[word.lower()] += 1
+    return counts
+
+
+class Circle(object):
+    def __init__(self, radius=1.5):
+        self.radius = radius
+
+    def area(self):
+        return math.pi * self.radius ** 2
+
+
+if __name__ == "__main__":
+    print(word_count('the quick brown fox'), Circle(3).area())
@@ -0,0 +1,18 @@
+use std::collections::HashMap;
+
+/// Counts how often every word occurs.
+pub fn word_count(text: &str) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for word in text.split_whitespace() {
+        *counts.entry(word.to_lowercase()).or_insert(0) += 1;
+    }
+    counts
+}
+
+fn main() {
+    let counts = word_count("the quick brown fox jumps over the lazy dog");
+    let limit: u32 = 42;
+    if counts.len() > 3 && limit != 0 {
+        println!("{} distinct words, 0x{:x}", counts.len(), 255);
+    }
+}
@@ -0,0 +1,13 @@
+#!/bin/sh
+# Prints the ten largest files below a directory.
+dir="${1:-.}"
+count=10
+
+if [ ! -d "$dir" ]; then
+    echo "not a directory: $dir" >&2
+    exit 1
+fi
+
+for file in $(find "$dir" -type f); do
+    du -k "$file"
+done | sort -rn | head -n $count
@@ -0,0 +1,178 @@
+use rand::Rng;
+use std::cmp::Ordering;
+use stats::{stddev, mean};
+
+use genetic::{Individual, Generation, ProblemDescription, diversity, evaluate,
+              evaluate_genotype};
+use optimizer::{Optimizer, ContinuousProblem, perturb};
+
+/// Simulated annealing of a single point. Both the temperature and the size
+/// of a move are scaled by the heat. The initial temperature is the standard
+/// deviation of the fitness of random genotypes. Moves which are worse by
+/// `Individual::compare` are only accepted between feasible genotypes, with
+/// a probability that falls with their difference in fitness.
+pub struct Annealing<P: ContinuousProblem> {
+    /// Moves per step.
+    pub moves: usize,
+    /// Standard deviation of a move at a heat of 1, relative to the range of
+    /// each coordinate.
+    pub move_size: f32,
+    /// Calibrated on the first step after the problem changed.
+    temperature: Option<f32>,
+    point: Vec<f32>,
+    current: Individual<P::Genotype>,
+    best: Individual<P::Genotype>,
+    candidates: Vec<Individual<P::Genotype>>,
+    problem_description: P,
+}
+
+impl<P: ContinuousProblem> Annealing<P> {
+    pub fn new<R: Rng>(moves: usize, problem_description: P, rng: &mut R) -> Annealing<P> {
+        let genotype = problem_description.random_genotype(rng);
+        let current = evaluate_genotype(&problem_description, genotype);
+        Annealing {
+            moves: moves.max(1),
+            move_size: 0.05,
+            temperature: None,
+            point: problem_description.encode(&current.genotype),
+            best: current.clone(),
+            current: current,
+            candidates: vec![],
+            problem_description: problem_description,
+        }
+    }
+
+    fn calibrate<R: Rng>(&mut self, rng: &mut R) -> f32 {
+        let fitness: Vec<f32> = (0..self.moves.max(2))
+                                    .map(|_| {
+                                        let g = self.problem_description.random_genotype(rng);
+                                        self.problem_description.calculate_fitness(&g)
+                                    })
+                                    .collect();
+        (stddev(fitness.into_iter()) as f32).max(1e-6)
+    }
+}
+
+impl<P: ContinuousProblem> Optimizer for Annealing<P> {
+    type Problem = P;
+
+    fn problem_description(&self) -> &P {
+        &self.problem_description
+    }
+
+    fn set_problem_description(&mut self, problem_description: P) {
+        evaluate(&problem_description, &mut self.current);
+        evaluate(&problem_description, &mut self.best);
+        self.temperature = None;
+        self.problem_description = problem_description;
+    }
+
+    /// Keeps the temperature.
+    fn update_problem<F: FnOnce(&mut P)>(&mut self, update: F) {
+        update(&mut self.problem_description);
+        evaluate(&self.problem_description, &mut self.current);
+        evaluate(&self.problem_description, &mut self.best);
+    }
+
+    fn step<R: Rng>(&mut self, heat: f32, rng: &mut R) -> Generation<P::Genotype> {
+        let initial_temperature = match self.temperature {
+            Some(t) => t,
+            None => self.calibrate(rng),
+        };
+        self.temperature = Some(initial_temperature);
+        let temperature = initial_temperature * heat;
+        let bounds = self.problem_description.bounds();
+
+        let mut accepted = 0;
+        let mut candidates = Vec::with_capacity(self.moves);
+        for _ in 0..self.moves {
+            let point = perturb(&self.point, &bounds, self.move_size * heat, rng);
+            let genotype = self.problem_description.decode(&point);
+            let candidate = evaluate_genotype(&self.problem_description, genotype);
+
+            let accept = match candidate.compare(&self.current) {
+                Ordering::Greater => {
+                    // worse at a priority level with a higher total still counts as worse
+                    let delta = -(candidate.fitness() - self.current.fitness()).abs();
+                    candidate.is_feasible() && rng.gen::<f32>() < (delta / temperature).exp()
+                }
+                _ => true,
+            };
+            if accept {
+                accepted += 1;
+                self.point = point;
+                self.current = candidate.clone();
+                if candidate.compare(&self.best) == Ordering::Less {
+                    self.best = candidate.clone();
+                }
+            }
+            candidates.push(candidate);
+        }
+
+        let mean_fitness = mean(candidates.iter().map(|i| i.fitness())) as f32;
+        let sd_fitness = stddev(candidates.iter().map(|i| i.fitness())) as f32;
+        self.candidates = candidates;
+        Generation {
+            best: self.best.clone(),
+            mean_fitness: mean_fitness,
+            sd_fitness: sd_fitness,
+            success_rate: Some(accepted as f32 / self.moves as f32),
+            reseeded: 0,
+            evaluated: self.candidates.clone(),
+        }
+    }
+
+    /// Diversity of the latest candidates.
+    fn diversity(&self) -> f32 {
+        diversity(&self.candidates)
+    }
+
+    /// Continues from the first genotype.
+    fn seed<R: Rng>(&mut self, genotypes: &[P::Genotype], _fraction: f32, _rng: &mut R) {
+        if let Some(genotype) = genotypes.first() {
+            self.point = self.problem_description.encode(genotype);
+            self.current = evaluate_genotype(&self.problem_description, genotype.clone());
+            if self.current.compare(&self.best) == Ordering::Less {
+                self.best = self.current.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use optimizer::Optimizer;
+    use optimizer::test::{Sphere, HalfSphere};
+    use genetic::HeatSchedule;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn converges_on_sphere() {
+        let seed: &[usize] = &[13];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut sa = Annealing::new(20, Sphere(4), &mut rng);
+        let schedule = HeatSchedule::Exponential(30);
+        let mut best = ::std::f32::MIN;
+        for i in 0..300 {
+            best = sa.step(schedule.heat(i), &mut rng).best.fitness();
+        }
+        assert!(best > -1e-2, "best fitness {}", best);
+    }
+
+    #[test]
+    fn respects_constraints() {
+        let seed: &[usize] = &[13];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut sa = Annealing::new(20, HalfSphere(4), &mut rng);
+        let schedule = HeatSchedule::Exponential(30);
+        let mut best = None;
+        for i in 0..300 {
+            best = Some(sa.step(schedule.heat(i), &mut rng).best);
+        }
+        let best = best.unwrap();
+        // the unconstrained optimum has x[0] = 0.3
+        assert!(best.is_feasible(), "violation {}", best.violation());
+        assert!(best.genotype.0[0] > -0.1, "x[0] = {}", best.genotype.0[0]);
+    }
+}
@@ -0,0 +1,102 @@
+use std::f64::INFINITY;
+
+/// Solves the assignment problem for a square cost matrix with the Hungarian
+/// method in O(n³). Returns the column assigned to every row, such that the
+/// summed cost is minimal. NaN costs are treated as very expensive.
+///
+/// Panics if the matrix is not square.
+pub fn min_cost_assignment(cost: &[Vec<f32>]) -> Vec<usize> {
+    let n = cost.len();
+    assert!(cost.iter().all(|row| row.len() == n),
+            "the cost matrix has to be square");
+    let cost_at = |i: usize, j: usize| -> f64 {
+        let c = cost[i][j];
+        if c.is_nan() {
+            ::std::f32::MAX as f64
+        } else {
+            c as f64
+        }
+    };
+
+    // 1-based, index 0 is a sentinel column
+    let mut u = vec![0.0; n + 1];
+    let mut v = vec![0.0; n + 1];
+    let mut row_of_column = vec![0; n + 1];
+    let mut way = vec![0; n + 1];
+    for i in 1..n + 1 {
+        row_of_column[0] = i;
+        let mut j0 = 0;
+        let mut min_v = vec![INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[j0] = true;
+            let i0 = row_of_column[j0];
+            let mut delta = INFINITY;
+            let mut j1 = 0;
+            for j in 1..n + 1 {
+                if !used[j] {
+                    let reduced = cost_at(i0 - 1, j - 1) - u[i0] - v[j];
+                    if reduced < min_v[j] {
+                        min_v[j] = reduced;
+                        way[j] = j0;
+                    }
+                    if min_v[j] < delta {
+                        delta = min_v[j];
+                        j1 = j;
+                    }
+                }
+            }
+            for j in 0..n + 1 {
+                if used[j] {
+                    u[row_of_column[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_v[j] -= delta;
+                }
+            }
+            j0 = j1;
+            if row_of_column[j0] == 0 {
+                break;
+            }
+        }
+        loop {
+            let j1 = way[j0];
+            row_of_column[j0] = row_of_column[j1];
+            j0 = j1;
+            if j0 == 0 {
+                break;
+            }
+        }
+    }
+
+    let mut assignment = vec![0; n];
+    for j in 1..n + 1 {
+        if row_of_column[j] != 0 {
+            assignment[row_of_column[j] - 1] = j - 1;
+        }
+    }
+    assignment
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn assignment() {
+        let cost = vec![vec![4.0, 1.0, 3.0], vec![2.0, 0.0, 5.0], vec![3.0, 2.0, 2.0]];
+        // 1 + 2 + 2 = 5 is optimal
+        assert_eq!(min_cost_assignment(&cost), vec![1, 0, 2]);
+    }
+
+    #[test]
+    fn assignment_empty() {
+        assert_eq!(min_cost_assignment(&[]), Vec::<usize>::new());
+    }
+
+    #[test]
+    #[should_panic]
+    fn assignment_not_square() {
+        min_cost_assignment(&[vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]);
+    }
+}
@@ -0,0 +1,386 @@
+use rand::{SeedableRng, StdRng};
+use stats::{OnlineStats, MinMax};
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+
+use fitness::ColorSchemeProblemDescription;
+use history::{FitnessHistory, Record};
+use genetic::{HeatSchedule, StepSize};
+use optimizer::{Optimizer, OptimizerKind, ColorSchemeOptimizer};
+use mutation::MutationOperator;
+use selection::SelectionStrategy;
+
+/// The optimizer settings which are varied in a benchmark. Every combination
+/// is run once per seed.
+#[derive(Clone, Debug)]
+pub struct Grid {
+    pub population_sizes: Vec<usize>,
+    pub generations: Vec<usize>,
+    pub optimizers: Vec<OptimizerKind>,
+    pub selections: Vec<SelectionStrategy>,
+    pub mutations: Vec<MutationOperator>,
+    pub step_sizes: Vec<StepSize>,
+    pub mutation_indices: Vec<f32>,
+    /// Whether the fitness cache is enabled, to measure its speedup.
+    pub fitness_caches: Vec<bool>,
+    /// Used by all configurations.
+    pub heat: HeatSchedule,
+    pub seeds: Vec<usize>,
+    /// A run has reached the target when its best fitness is at least this high.
+    pub target_fitness: Option<f32>,
+}
+
+impl Default for Grid {
+    fn default() -> Grid {
+        Grid {
+            population_sizes: vec![50],
+            generations: vec![500],
+            optimizers: vec![OptimizerKind::default()],
+            selections: vec![SelectionStrategy::default()],
+            mutations: vec![MutationOperator::default()],
+            step_sizes: vec![StepSize::default()],
+            mutation_indices: vec![1.0],
+            fitness_caches: vec![true],
+            heat: HeatSchedule::default(),
+            seeds: (0..20).collect(),
+            target_fitness: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    pub population_size: usize,
+    pub generations: usize,
+    pub optimizer: OptimizerKind,
+    pub selection: SelectionStrategy,
+    pub mutation: MutationOperator,
+    pub step_size: StepSize,
+    pub mutation_index: f32,
+    pub fitness_cache: bool,
+    pub heat: HeatSchedule,
+}
+
+/// The heat is left out, since all configurations share it.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f,
+                    "{} with {} individuals for {} generations",
+                    self.optimizer,
+                    self.population_size,
+                    self.generations));
+        if self.optimizer == OptimizerKind::Genetic {
+            try!(write!(f,
+                        ", selection {}, mutation {}, step size {}, mutation index {}",
+                        self.selection,
+                        self.mutation,
+                        self.step_size,
+                        self.mutation_index));
+        }
+        write!(f, ", fitness cache {}", if self.fitness_cache { "on" } else { "off" })
+    }
+}
+
+impl Grid {
+    pub fn configs(&self) -> Vec<Config> {
+        let mut configs = vec![];
+        for &population_size in self.population_sizes.iter() {
+            for &generations in self.generations.iter() {
+                for &optimizer in self.optimizers.iter() {
+                    // the operator settings only apply to the genetic algorithm
+                    let genetic = optimizer == OptimizerKind::Genetic;
+                    let defaults = Grid::default();
+                    let (selections, mutations, step_sizes, mutation_indices) = if genetic {
+                        (&self.selections,
+                         &self.mutations,
+                         &self.step_sizes,
+                         &self.mutation_indices)
+                    } else {
+                        (&defaults.selections,
+                         &defaults.mutations,
+                         &defaults.step_sizes,
+                         &defaults.mutation_indices)
+                    };
+                    for &selection in selections.iter() {
+                        for &mutation in mutations.iter() {
+                            for &step_size in step_sizes.iter() {
+                                for &mutation_index in mutation_indices.iter() {
+                                    for &fitness_cache in self.fitness_caches.iter() {
+                                        configs.push(Config {
+                                            population_size: population_size,
+                                            generations: generations,
+                                            optimizer: optimizer,
+                                            selection: selection,
+                                            mutation: mutation,
+                                            step_size: step_size,
+                                            mutation_index: mutation_index,
+                                            fitness_cache: fitness_cache,
+                                            heat: self.heat,
+                                        });
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        configs
+    }
+}
+
+pub struct ConfigResult {
+    pub config: Config,
+    pub runs: usize,
+    pub best: f32,
+    pub mean: f32,
+    pub sd: f32,
+    /// Mean seconds per run.
+    pub seconds: f64,
+    /// Number of runs which reached the target fitness.
+    pub reached: usize,
+    /// Mean seconds and generations until the target was reached, over the
+    /// runs which reached it.
+    pub time_to_target: Option<(f64, f64)>,
+}
+
+struct RunResult {
+    fitness: f32,
+    seconds: f64,
+    target_reached: Option<(f64, usize)>,
+}
+
+/// Records every generation to the history, if any, as the given run.
+fn run(config: &Config,
+       descr: &ColorSchemeProblemDescription,
+       seed: usize,
+       target_fitness: Option<f32>,
+       history: Option<(&mut FitnessHistory, usize)>)
+       -> io::Result<RunResult> {
+    let mut history = history;
+    let seed: &[usize] = &[seed];
+    let mut rng: StdRng = SeedableRng::from_seed(seed);
+    let problem = descr.clone();
+    problem.cache.borrow_mut().enabled = config.fitness_cache;
+    let mut p = ColorSchemeOptimizer::new(config.optimizer,
+                                          config.population_size,
+                                          problem,
+                                          &mut rng);
+    if let Some(ga) = p.genetic() {
+        ga.selection = config.selection;
+        ga.mutation = config.mutation;
+        ga.step_size = config.step_size;
+        ga.mutation_index = config.mutation_index;
+    }
+
+    let start = Instant::now();
+    let mut best = None;
+    let mut target_reached = None;
+    for i in 0..config.generations {
+        let heat = config.heat.heat(i);
+        let generation = p.step(heat, &mut rng);
+        let fitness = generation.best.fitness();
+        if let Some((ref mut history, run)) = history {
+            let data = generation.best.genotype.fitness_data(descr);
+            try!(history.record(&Record {
+                run: run,
+                generation: i,
+                best_fitness: fitness,
+                mean_fitness: generation.mean_fitness,
+                sd_fitness: generation.sd_fitness,
+                heat: heat,
+                targets: descr.fitness_targets
+                              .values()
+                              .map(|t| (t.name(), t.value(&data).unwrap_or(::std::f32::NAN)))
+                              .collect(),
+                diversity: p.diversity(),
+                time: seconds_since(start),
+            }));
+        }
+        if let Some(target) = target_fitness {
+            if target_reached.is_none() && fitness >= target {
+                target_reached = Some((seconds_since(start), i));
+            }
+        }
+        best = Some(fitness);
+    }
+
+    Ok(RunResult {
+        fitness: best.unwrap_or(::std::f32::NAN),
+        seconds: seconds_since(start),
+        target_reached: target_reached,
+    })
+}
+
+fn seconds_since(start: Instant) -> f64 {
+    let elapsed = start.elapsed();
+    elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9
+}
+
+/// Runs every configuration of the grid once per seed and prints a progress
+/// line per configuration. The runs are numbered in the history.
+pub fn run_grid(grid: &Grid,
+                descr: &ColorSchemeProblemDescription,
+                mut history: Option<&mut FitnessHistory>)
+                -> io::Result<Vec<ConfigResult>> {
+    let mut run_count = 0;
+    let mut results = vec![];
+    for config in grid.configs().iter() {
+        let mut fitness_stats = OnlineStats::new();
+        let mut fitness_minmax = MinMax::new();
+        let mut time_stats = OnlineStats::new();
+        let mut generation_stats = OnlineStats::new();
+        let mut seconds_stats = OnlineStats::new();
+        for &seed in grid.seeds.iter() {
+            let history = history.as_mut().map(|history| (&mut **history, run_count));
+            let result = try!(run(config, descr, seed, grid.target_fitness, history));
+            run_count += 1;
+            fitness_stats.add(result.fitness);
+            fitness_minmax.add(result.fitness);
+            seconds_stats.add(result.seconds);
+            if let Some((secs, generation)) = result.target_reached {
+                time_stats.add(secs);
+                generation_stats.add(generation);
+            }
+        }
+        println!("finished {}", config);
+
+        results.push(ConfigResult {
+            config: *config,
+            runs: grid.seeds.len(),
+            best: fitness_minmax.max().cloned().unwrap_or(::std::f32::NAN),
+            mean: fitness_stats.mean() as f32,
+            sd: fitness_stats.stddev() as f32,
+            seconds: seconds_stats.mean(),
+            reached: time_stats.len(),
+            time_to_target: if time_stats.len() > 0 {
+                Some((time_stats.mean(), generation_stats.mean()))
+            } else {
+                None
+            },
+        });
+    }
+    Ok(results)
+}
+
+pub fn print_table(results: &[ConfigResult]) {
+    println!("{:>6} {:>6} {:>12} {:>16} {:>12} {:>18} {:>5} {:>5} {:>4} {:>11} {:>11} {:>9} \
+              {:>9} {:>7} {:>9} {:>7}",
+             "pop",
+             "gens",
+             "optimizer",
+             "selection",
+             "mutation",
+             "step size",
+             "mut",
+             "cache",
+             "runs",
+             "best",
+             "mean",
+             "sd",
+             "secs",
+             "reached",
+             "time",
+             "gens");
+    for r in results.iter() {
+        let (time, generations) = match r.time_to_target {
+            Some((time, generations)) => {
+                (format!("{:.3}", time), format!("{:.1}", generations))
+            }
+            None => ("-".to_string(), "-".to_string()),
+        };
+        println!("{:>6} {:>6} {:>12} {:>16} {:>12} {:>18} {:>5.2} {:>5} {:>4} {:>11.3} {:>11.3} \
+                  {:>9.3} {:>9.3} {:>7} {:>9} {:>7}",
+                 r.config.population_size,
+                 r.config.generations,
+                 r.config.optimizer.to_string(),
+                 r.config.selection.to_string(),
+                 r.config.mutation.to_string(),
+                 r.config.step_size.to_string(),
+                 r.config.mutation_index,
+                 if r.config.fitness_cache { "on" } else { "off" },
+                 r.runs,
+                 r.best,
+                 r.mean,
+                 r.sd,
+                 r.seconds,
+                 r.reached,
+                 time,
+                 generations);
+    }
+}
+
+pub fn write_csv(results: &[ConfigResult], path: &str) -> io::Result<()> {
+    let mut out = BufWriter::new(try!(File::create(path)));
+    try!(writeln!(out,
+                  "population_size,generations,optimizer,selection,mutation,step_size,\
+                   mutation_index,fitness_cache,heat,runs,best,mean,sd,seconds,reached,\
+                   time_to_target,generations_to_target"));
+    for r in results.iter() {
+        let (time, generations) = match r.time_to_target {
+            Some((time, generations)) => (format!("{}", time), format!("{}", generations)),
+            None => (String::new(), String::new()),
+        };
+        try!(writeln!(out,
+                      "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                      r.config.population_size,
+                      r.config.generations,
+                      r.config.optimizer,
+                      r.config.selection,
+                      r.config.mutation,
+                      r.config.step_size,
+                      r.config.mutation_index,
+                      r.config.fitness_cache,
+                      r.config.heat,
+                      r.runs,
+                      r.best,
+                      r.mean,
+                      r.sd,
+                      r.seconds,
+                      r.reached,
+                      time,
+                      generations));
+    }
+    out.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use optimizer::OptimizerKind;
+    use selection::SelectionStrategy;
+
+    #[test]
+    fn cartesian_product() {
+        let grid = Grid {
+            population_sizes: vec![10, 20],
+            optimizers: vec![OptimizerKind::Genetic, OptimizerKind::CmaEs],
+            selections: vec![SelectionStrategy::Roulette, SelectionStrategy::Tournament(2)],
+            fitness_caches: vec![true, false],
+            ..Grid::default()
+        };
+        let configs = grid.configs();
+        // the selections only multiply the genetic configurations
+        assert_eq!(configs.len(), 2 * (2 * 2 + 2));
+        let first = configs[0];
+        assert_eq!((first.population_size, first.optimizer, first.selection, first.fitness_cache),
+                   (10, OptimizerKind::Genetic, SelectionStrategy::Roulette, true));
+        let last = configs[configs.len() - 1];
+        assert_eq!((last.population_size, last.optimizer, last.selection, last.fitness_cache),
+                   (20, OptimizerKind::CmaEs, SelectionStrategy::default(), false));
+        for &population_size in [10, 20].iter() {
+            for &fitness_cache in [true, false].iter() {
+                for &selection in grid.selections.iter() {
+                    assert!(configs.iter().any(|c| {
+                        c.population_size == population_size && c.fitness_cache == fitness_cache &&
+                        c.optimizer == OptimizerKind::Genetic &&
+                        c.selection == selection
+                    }));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use palette::{Lab, Lch};
+use std::collections::HashMap;
+use std::mem;
+
+use color::{adjust_color_space, ciede2000, distance};
+
+/// The values the fitness calculation derives from a single color.
+#[derive(Clone, Copy, Debug)]
+pub struct ColorFeatures {
+    /// The color clamped to RGB, which distances are measured on.
+    pub clamped: Lab,
+    pub lch: Lch,
+}
+
+impl ColorFeatures {
+    pub fn new(color: &Lab) -> ColorFeatures {
+        ColorFeatures {
+            clamped: adjust_color_space(color),
+            lch: (*color).into(),
+        }
+    }
+}
+
+/// The exact bits of a color, for looking it up by identity.
+pub type Key = [u32; 3];
+
+/// The key of a color. Colors with the same key are the same color.
+pub fn key(color: &Lab) -> Key {
+    unsafe {
+        [mem::transmute::<f32, u32>(color.l),
+         mem::transmute::<f32, u32>(color.a),
+         mem::transmute::<f32, u32>(color.b)]
+    }
+}
+
+/// Memoizes the features of colors and the distances between them by the
+/// exact bits of the colors. Genes which survive a generation unchanged, e.g.
+/// in crossover children which are not mutated, are looked up instead of
+/// recalculated, and every color is clamped to RGB only once.
+#[derive(Clone, Debug)]
+pub struct FitnessCache {
+    /// Without the cache every distance clamps both colors again.
+    pub enabled: bool,
+    /// Both tables are cleared when either grows beyond this many entries.
+    pub capacity: usize,
+    colors: HashMap<Key, ColorFeatures>,
+    distances: HashMap<(Key, Key), f32>,
+    hits: usize,
+    misses: usize,
+}
+
+impl FitnessCache {
+    pub fn new() -> FitnessCache {
+        FitnessCache {
+            enabled: true,
+            capacity: 1 << 18,
+            colors: HashMap::new(),
+            distances: HashMap::new(),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn features(&mut self, color: &Lab) -> ColorFeatures {
+        if !self.enabled {
+            return ColorFeatures::new(color);
+        }
+        if self.colors.len() >= self.capacity {
+            self.clear();
+        }
+        *self.colors.entry(key(color)).or_insert_with(|| ColorFeatures::new(color))
+    }
+
+    /// The same as `color::distance`.
+    pub fn distance(&mut self, col1: &Lab, col2: &Lab) -> f32 {
+        if !self.enabled {
+            return distance(col1, col2);
+        }
+        let pair = (key(col1), key(col2));
+        if let Some(&dist) = self.distances.get(&pair) {
+            self.hits += 1;
+            return dist;
+        }
+        self.misses += 1;
+        let dist = ciede2000(&self.features(col1).clamped, &self.features(col2).clamped);
+        if self.distances.len() >= self.capacity {
+            self.clear();
+        }
+        self.distances.insert(pair, dist);
+        dist
+    }
+
+    /// Fraction of the distances which were looked up.
+    pub fn hit_rate(&self) -> Option<f32> {
+        match self.hits + self.misses {
+            0 => None,
+            total => Some(self.hits as f32 / total as f32),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.colors.clear();
+        self.distances.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use color::distance;
+    use palette::Lab;
+
+    #[test]
+    fn cached_distance_matches() {
+        let mut cache = FitnessCache::new();
+        let a = Lab::new(0.3, 0.9, -0.8);
+        let b = Lab::new(0.7, -0.2, 0.4);
+        assert_eq!(cache.distance(&a, &b), distance(&a, &b));
+        assert_eq!(cache.distance(&a, &b), distance(&a, &b));
+        assert_eq!(cache.hit_rate(), Some(0.5));
+    }
+}
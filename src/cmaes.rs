@@ -0,0 +1,392 @@
+use rand::Rng;
+use rand::distributions::normal::StandardNormal;
+use std::cmp::Ordering;
+use stats::{stddev, mean};
+
+use genetic::{Individual, Generation, ProblemDescription, diversity, evaluate,
+              evaluate_genotype};
+use optimizer::{Optimizer, ContinuousProblem, clamp};
+
+/// Covariance matrix adaptation evolution strategy with the default
+/// parameters from Hansen's tutorial. It adapts its own step size, so the
+/// heat is ignored. Samples outside of the bounds are clamped before they are
+/// evaluated and used for the update. Samples are ranked by
+/// `Individual::compare`.
+pub struct CmaEs<P: ContinuousProblem> {
+    lambda: usize,
+    weights: Vec<f64>,
+    mueff: f64,
+    cc: f64,
+    cs: f64,
+    c1: f64,
+    cmu: f64,
+    damps: f64,
+    chi_n: f64,
+    mean: Vec<f64>,
+    sigma: f64,
+    initial_sigma: f64,
+    covariance: Vec<Vec<f64>>,
+    /// Eigenvectors of the covariance as columns.
+    basis: Vec<Vec<f64>>,
+    /// Square roots of the eigenvalues.
+    scales: Vec<f64>,
+    pc: Vec<f64>,
+    ps: Vec<f64>,
+    generation: usize,
+    samples: Vec<Individual<P::Genotype>>,
+    best: Option<Individual<P::Genotype>>,
+    problem_description: P,
+}
+
+impl<P: ContinuousProblem> CmaEs<P> {
+    /// Starts at a random genotype. At least `4 + 3 ln n` offspring are
+    /// sampled per generation.
+    pub fn new<R: Rng>(offspring: usize, problem_description: P, rng: &mut R) -> CmaEs<P> {
+        let start = problem_description.random_genotype(rng);
+        let mean: Vec<f64> = problem_description.encode(&start)
+                                                .iter()
+                                                .map(|&x| x as f64)
+                                                .collect();
+        let n = mean.len() as f64;
+        let lambda = offspring.max(4 + (3.0 * n.ln()).floor() as usize);
+        let mu = lambda / 2;
+        let raw: Vec<f64> = (0..mu)
+                                .map(|i| (mu as f64 + 0.5).ln() - (i as f64 + 1.0).ln())
+                                .collect();
+        let sum = raw.iter().sum::<f64>();
+        let weights: Vec<f64> = raw.iter().map(|w| w / sum).collect();
+        let mueff = 1.0 / weights.iter().map(|w| w * w).sum::<f64>();
+        let cs = (mueff + 2.0) / (n + mueff + 5.0);
+        let c1 = 2.0 / ((n + 1.3) * (n + 1.3) + mueff);
+        let initial_sigma = 0.3;
+
+        let mut cma = CmaEs {
+            lambda: lambda,
+            weights: weights,
+            mueff: mueff,
+            cc: (4.0 + mueff / n) / (n + 4.0 + 2.0 * mueff / n),
+            cs: cs,
+            c1: c1,
+            cmu: (1.0 - c1).min(2.0 * (mueff - 2.0 + 1.0 / mueff) /
+                                ((n + 2.0) * (n + 2.0) + mueff)),
+            damps: 1.0 + 2.0 * (((mueff - 1.0) / (n + 1.0)).sqrt() - 1.0).max(0.0) + cs,
+            chi_n: n.sqrt() * (1.0 - 1.0 / (4.0 * n) + 1.0 / (21.0 * n * n)),
+            mean: mean,
+            sigma: initial_sigma,
+            initial_sigma: initial_sigma,
+            covariance: vec![],
+            basis: vec![],
+            scales: vec![],
+            pc: vec![],
+            ps: vec![],
+            generation: 0,
+            samples: vec![],
+            best: None,
+            problem_description: problem_description,
+        };
+        cma.restart();
+        cma
+    }
+
+    /// Resets step size, covariance and evolution paths, keeping the mean.
+    fn restart(&mut self) {
+        let n = self.mean.len();
+        self.sigma = self.initial_sigma;
+        self.covariance = identity(n);
+        self.basis = identity(n);
+        self.scales = vec![1.0; n];
+        self.pc = vec![0.0; n];
+        self.ps = vec![0.0; n];
+        self.generation = 0;
+    }
+
+    pub fn sigma(&self) -> f32 {
+        self.sigma as f32
+    }
+}
+
+impl<P: ContinuousProblem> Optimizer for CmaEs<P> {
+    type Problem = P;
+
+    fn problem_description(&self) -> &P {
+        &self.problem_description
+    }
+
+    /// Restarts the adaptation around the current mean.
+    fn set_problem_description(&mut self, problem_description: P) {
+        if let Some(ref mut best) = self.best {
+            evaluate(&problem_description, best);
+        }
+        self.problem_description = problem_description;
+        self.samples.clear();
+        self.restart();
+    }
+
+    /// Keeps the mean, step size and covariance.
+    fn update_problem<F: FnOnce(&mut P)>(&mut self, update: F) {
+        update(&mut self.problem_description);
+        if let Some(ref mut best) = self.best {
+            evaluate(&self.problem_description, best);
+        }
+        for sample in self.samples.iter_mut() {
+            evaluate(&self.problem_description, sample);
+        }
+    }
+
+    fn step<R: Rng>(&mut self, _heat: f32, rng: &mut R) -> Generation<P::Genotype> {
+        let n = self.mean.len();
+        let bounds = self.problem_description.bounds();
+
+        let mut points = Vec::with_capacity(self.lambda);
+        let mut samples = Vec::with_capacity(self.lambda);
+        for _ in 0..self.lambda {
+            let z: Vec<f64> = (0..n).map(|_| normal(rng)).collect();
+            let mut x: Vec<f32> = (0..n)
+                                      .map(|i| {
+                                          let y = (0..n)
+                                                      .map(|j| {
+                                                          self.basis[i][j] * self.scales[j] * z[j]
+                                                      })
+                                                      .sum::<f64>();
+                                          (self.mean[i] + self.sigma * y) as f32
+                                      })
+                                      .collect();
+            clamp(&mut x, &bounds);
+            let genotype = self.problem_description.decode(&x);
+            samples.push(evaluate_genotype(&self.problem_description, genotype));
+            points.push(x.iter().map(|&x| x as f64).collect::<Vec<f64>>());
+        }
+
+        let mut order: Vec<usize> = (0..self.lambda).collect();
+        order.sort_by(|&a, &b| samples[a].compare(&samples[b]));
+
+        // recombination of the mu best steps
+        let steps: Vec<Vec<f64>> = order.iter()
+                                        .take(self.weights.len())
+                                        .map(|&k| {
+                                            (0..n)
+                                                .map(|i| (points[k][i] - self.mean[i]) / self.sigma)
+                                                .collect()
+                                        })
+                                        .collect();
+        let step_w: Vec<f64> = (0..n)
+                                   .map(|i| {
+                                       steps.iter()
+                                            .zip(self.weights.iter())
+                                            .map(|(y, w)| w * y[i])
+                                            .sum()
+                                   })
+                                   .collect();
+        for i in 0..n {
+            self.mean[i] += self.sigma * step_w[i];
+        }
+
+        // evolution paths, with C^(-1/2) = B D^-1 B^T
+        let projected: Vec<f64> = (0..n)
+                                      .map(|j| {
+                                          let dot = (0..n)
+                                                        .map(|i| self.basis[i][j] * step_w[i])
+                                                        .sum::<f64>();
+                                          dot / self.scales[j]
+                                      })
+                                      .collect();
+        let whitened: Vec<f64> = (0..n)
+                                     .map(|i| (0..n).map(|j| self.basis[i][j] * projected[j]).sum())
+                                     .collect();
+        let ps_factor = (self.cs * (2.0 - self.cs) * self.mueff).sqrt();
+        for i in 0..n {
+            self.ps[i] = (1.0 - self.cs) * self.ps[i] + ps_factor * whitened[i];
+        }
+        let ps_norm = self.ps.iter().map(|x| x * x).sum::<f64>().sqrt();
+        self.generation += 1;
+        let hsig = ps_norm /
+                   (1.0 - (1.0 - self.cs).powi(2 * self.generation as i32)).sqrt() /
+                   self.chi_n < 1.4 + 2.0 / (n as f64 + 1.0);
+        let hsig = if hsig { 1.0 } else { 0.0 };
+        let pc_factor = hsig * (self.cc * (2.0 - self.cc) * self.mueff).sqrt();
+        for i in 0..n {
+            self.pc[i] = (1.0 - self.cc) * self.pc[i] + pc_factor * step_w[i];
+        }
+
+        // covariance: rank-one and rank-mu update
+        let correction = (1.0 - hsig) * self.cc * (2.0 - self.cc);
+        for i in 0..n {
+            for j in 0..n {
+                let rank_mu = steps.iter()
+                                   .zip(self.weights.iter())
+                                   .map(|(y, w)| w * y[i] * y[j])
+                                   .sum::<f64>();
+                self.covariance[i][j] = (1.0 - self.c1 - self.cmu) * self.covariance[i][j] +
+                                        self.c1 *
+                                        (self.pc[i] * self.pc[j] +
+                                         correction * self.covariance[i][j]) +
+                                        self.cmu * rank_mu;
+            }
+        }
+
+        self.sigma *= ((self.cs / self.damps) * (ps_norm / self.chi_n - 1.0)).exp();
+        self.sigma = self.sigma.max(1e-8).min(1.0);
+
+        let (eigenvalues, eigenvectors) = symmetric_eigen(&self.covariance);
+        self.scales = eigenvalues.iter().map(|&e| e.max(1e-20).sqrt()).collect();
+        self.basis = eigenvectors;
+
+        let generation_best = samples[order[0]].clone();
+        let best = match self.best.take() {
+            Some(best) => {
+                match generation_best.compare(&best) {
+                    Ordering::Less => generation_best,
+                    _ => best,
+                }
+            }
+            None => generation_best,
+        };
+        self.best = Some(best.clone());
+
+        let mean_fitness = mean(samples.iter().map(|i| i.fitness())) as f32;
+        let sd_fitness = stddev(samples.iter().map(|i| i.fitness())) as f32;
+        self.samples = samples;
+
+        Generation {
+            best: best,
+            mean_fitness: mean_fitness,
+            sd_fitness: sd_fitness,
+            success_rate: None,
+            reseeded: 0,
+            evaluated: self.samples.clone(),
+        }
+    }
+
+    /// Diversity of the latest samples.
+    fn diversity(&self) -> f32 {
+        diversity(&self.samples)
+    }
+
+    /// Restarts around the first genotype.
+    fn seed<R: Rng>(&mut self, genotypes: &[P::Genotype], _fraction: f32, _rng: &mut R) {
+        if let Some(genotype) = genotypes.first() {
+            self.mean = self.problem_description
+                            .encode(genotype)
+                            .iter()
+                            .map(|&x| x as f64)
+                            .collect();
+            self.restart();
+        }
+    }
+}
+
+fn normal<R: Rng>(rng: &mut R) -> f64 {
+    let StandardNormal(x) = rng.gen::<StandardNormal>();
+    x
+}
+
+fn identity(n: usize) -> Vec<Vec<f64>> {
+    (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect()
+}
+
+/// Eigenvalues and eigenvectors (as columns) of a symmetric matrix, by cyclic
+/// Jacobi rotations.
+fn symmetric_eigen(matrix: &[Vec<f64>]) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = matrix.len();
+    let mut a = matrix.to_vec();
+    let mut v = identity(n);
+    for _ in 0..100 {
+        let off_diagonal = (0..n)
+                               .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+                               .map(|(i, j)| a[i][j] * a[i][j])
+                               .sum::<f64>();
+        if off_diagonal < 1e-22 {
+            break;
+        }
+        for p in 0..n {
+            for q in p + 1..n {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for k in 0..n {
+                    let (kp, kq) = (a[k][p], a[k][q]);
+                    a[k][p] = c * kp - s * kq;
+                    a[k][q] = s * kp + c * kq;
+                }
+                for k in 0..n {
+                    let (pk, qk) = (a[p][k], a[q][k]);
+                    a[p][k] = c * pk - s * qk;
+                    a[q][k] = s * pk + c * qk;
+                }
+                for k in 0..n {
+                    let (kp, kq) = (v[k][p], v[k][q]);
+                    v[k][p] = c * kp - s * kq;
+                    v[k][q] = s * kp + c * kq;
+                }
+            }
+        }
+    }
+    ((0..n).map(|i| a[i][i]).collect(), v)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use optimizer::Optimizer;
+    use optimizer::test::{Sphere, HalfSphere};
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn eigen_decomposition() {
+        let (values, vectors) = symmetric_eigen(&[vec![2.0, 1.0], vec![1.0, 2.0]]);
+        let mut sorted = values.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert!((sorted[0] - 1.0).abs() < 1e-9 && (sorted[1] - 3.0).abs() < 1e-9);
+        // A v = lambda v
+        for k in 0..2 {
+            let av0 = 2.0 * vectors[0][k] + vectors[1][k];
+            assert!((av0 - values[k] * vectors[0][k]).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn converges_on_sphere() {
+        let seed: &[usize] = &[11];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut cma = CmaEs::new(10, Sphere(4), &mut rng);
+        let mut best = ::std::f32::MIN;
+        for _ in 0..200 {
+            best = cma.step(1.0, &mut rng).best.fitness();
+        }
+        assert!(best > -1e-4, "best fitness {}", best);
+    }
+
+    #[test]
+    fn update_keeps_adaptation() {
+        let seed: &[usize] = &[11];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut cma = CmaEs::new(10, Sphere(4), &mut rng);
+        for _ in 0..50 {
+            cma.step(1.0, &mut rng);
+        }
+        let sigma = cma.sigma();
+        cma.update_problem(|_| {});
+        assert_eq!(cma.sigma(), sigma);
+        cma.set_problem_description(Sphere(4));
+        assert_eq!(cma.sigma(), 0.3);
+    }
+
+    #[test]
+    fn respects_constraints() {
+        let seed: &[usize] = &[11];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut cma = CmaEs::new(10, HalfSphere(4), &mut rng);
+        let mut best = None;
+        for _ in 0..200 {
+            best = Some(cma.step(1.0, &mut rng).best);
+        }
+        let best = best.unwrap();
+        // the unconstrained optimum has x[0] = 0.3
+        assert!(best.is_feasible(), "violation {}", best.violation());
+        assert!(best.genotype.0[0] > -0.05, "x[0] = {}", best.genotype.0[0]);
+    }
+}
@@ -19,16 +19,31 @@ pub fn distance(col1: &Lab, col2: &Lab) -> f32 {
     ciede2000(&col1, &col2)
 }
 
-#[allow(dead_code)]
+/// Relative luminance as defined by WCAG 2.0, computed on the color clamped to RGB.
+pub fn relative_luminance(col: &Lab) -> f32 {
+    let mut rgb: Rgb = (*col).into();
+    rgb.clamp_self();
+    0.2126 * rgb.red + 0.7152 * rgb.green + 0.0722 * rgb.blue
+}
+
+/// WCAG 2.0 contrast ratio between 1 (no contrast) and 21 (black on white).
+pub fn contrast_ratio(col1: &Lab, col2: &Lab) -> f32 {
+    let l1 = relative_luminance(col1);
+    let l2 = relative_luminance(col2);
+    (l1.max(l2) + 0.05) / (l1.min(l2) + 0.05)
+}
+
 pub fn euclidean_distance(a: &Lab, b: &Lab) -> f32 {
     (((a.l - b.l) * 100.0).powi(2) + ((a.a - b.a) * 128.0).powi(2) + ((a.b - b.b) * 128.0).powi(2))
         .sqrt()
 }
 
+/// Linear color from 8 bit sRGB components.
+#[macro_export]
 macro_rules! srgb {
     ( $r:expr,$g:expr, $b:expr ) => {
         {
-    Srgb::new($r as f32 / 255.0, $g as f32 / 255.0, $b as f32 / 255.0)
+    $crate::palette::pixel::Srgb::new($r as f32 / 255.0, $g as f32 / 255.0, $b as f32 / 255.0)
         .to_linear()
         .into()
         }
@@ -205,6 +220,14 @@ mod test {
         assert_eq!((ciede2000(&lab1, &lab2) * 10000.0).round() / 10000.0, de);
     }
 
+    #[test]
+    fn contrast_ratio_black_white() {
+        let black = Lab::new(0.0, 0.0, 0.0);
+        let white = Lab::new(1.0, 0.0, 0.0);
+        assert_eq!((contrast_ratio(&black, &white) * 10.0).round() / 10.0, 21.0);
+        assert_eq!(contrast_ratio(&white, &white), 1.0);
+    }
+
     // from http://www.ece.rochester.edu/~gsharma/ciede2000/dataNprograms/ciede2000testdata.txt
     #[test]
     fn ciede2000_test1() {
@@ -1,29 +1,52 @@
 use palette::{Lab, Lch, Rgb};
-use fitness::{ColorSchemeProblemDescription, FitnessData, StatValues};
+use fitness::{ColorSchemeProblemDescription, FitnessData, StatValues, Normalization, Target};
 use fitness::Parameter::*;
 use color::*;
-use genetic::Genotype;
+use genetic::{Genotype, Population};
+use selection::SelectionStrategy;
+use crossover::CrossoverOperator;
+use mutation::MutationOperator;
+use assignment::min_cost_assignment;
+use cache::ColorFeatures;
 use rand::Rng;
-use rand::distributions::{Normal, IndependentSample};
 
 #[derive(Debug, Clone, Default)]
 pub struct ColorScheme {
     pub free_colors: Vec<Lab>,
-    fitness: f32,
 }
 
 impl ColorScheme {
-    pub fn preview(&self, descr: &ColorSchemeProblemDescription) {
-        for color in descr.fixed_colors.iter() {
-            print_color(color);
-        }
-        println!("");
+    pub fn new(free_colors: Vec<Lab>) -> ColorScheme {
+        ColorScheme { free_colors: free_colors }
+    }
+
+    /// Free colors in a stable order (by hue, then lightness), so that
+    /// previews don't reshuffle between generations.
+    pub fn sorted_free_colors(&self) -> Vec<Lab> {
         let mut sorted = self.free_colors.clone();
         sorted.sort_by_key(|&col| {
             let lch: Lch = col.into();
             (lch.hue.to_positive_degrees() * 100.0) as usize + (lch.l * 1000.0) as usize
         });
-        for color in sorted.iter() {
+        sorted
+    }
+
+    /// The order in which the free colors are shown and exported: by role if
+    /// the roles are named, sorted otherwise.
+    pub fn display_colors(&self, descr: &ColorSchemeProblemDescription) -> Vec<Lab> {
+        if descr.roles.is_empty() {
+            self.sorted_free_colors()
+        } else {
+            self.free_colors.clone()
+        }
+    }
+
+    pub fn preview(&self, descr: &ColorSchemeProblemDescription) {
+        for color in descr.fixed_colors.iter() {
+            print_color(color);
+        }
+        println!("");
+        for color in self.display_colors(descr).iter() {
             print_color(color);
         }
 
@@ -61,38 +84,55 @@ impl ColorScheme {
         // println!("");
     }
 
+    /// Every fixed color paired with every free color.
+    pub fn fixed_dist_pairs<'a>(&'a self,
+                                descr: &'a ColorSchemeProblemDescription)
+                                -> Vec<(&'a Lab, &'a Lab, f32)> {
+        descr.fixed_colors
+             .iter()
+             .flat_map(|col1| {
+                 self.free_colors
+                     .iter()
+                     .map(move |col2| (col1, col2, descr.distance(col1, col2)))
+             })
+             .collect()
+    }
+
+    /// Every unordered pair of distinct free colors.
+    pub fn free_dist_pairs<'a>(&'a self,
+                               descr: &ColorSchemeProblemDescription)
+                               -> Vec<(&'a Lab, &'a Lab, f32)> {
+        self.free_colors
+            .iter()
+            .enumerate()
+            .flat_map(|(i, col1)| {
+                self.free_colors
+                    .iter()
+                    .skip(i + 1)
+                    .map(move |col2| (col1, col2, descr.distance(col1, col2)))
+            })
+            .collect()
+    }
+
     pub fn fitness_data(&self, descr: &ColorSchemeProblemDescription) -> FitnessData {
-        let chroma: Vec<f32> = self.free_colors
-                                   .iter()
-                                   .map(|&col| {
-                                       let lch: Lch = col.into();
-                                       lch.chroma * 128.0
-                                   })
-                                   .collect();
+        let features: Vec<ColorFeatures> = {
+            let mut cache = descr.cache.borrow_mut();
+            self.free_colors.iter().map(|col| cache.features(col)).collect()
+        };
+        let chroma: Vec<f32> = features.iter().map(|f| f.lch.chroma * 128.0).collect();
 
         let luminance: Vec<f32> = self.free_colors.iter().map(|&col| col.l * 100.0).collect();
 
-        let fixed_dist: Vec<f32> = descr.fixed_colors
+        let fixed_dist: Vec<f32> = self.fixed_dist_pairs(descr)
                                         .iter()
-                                        .flat_map(|col1| {
-                                            self.free_colors
-                                                .iter()
-                                                .map(move |col2| distance(col1, col2))
-                                        })
+                                        .map(|&(_, _, dist)| dist)
                                         .collect();
 
-        let free_dist: Vec<f32> = self.free_colors
+        let free_dist: Vec<f32> = self.free_dist_pairs(descr)
                                       .iter()
-                                      .enumerate()
-                                      .flat_map(|(i, col1)| {
-                                          self.free_colors
-                                              .iter()
-                                              .skip(i + 1)
-                                              .map(move |col2| distance(col1, col2))
-                                      })
+                                      .map(|&(_, _, dist)| dist)
                                       .collect();
 
-
         let mut data = FitnessData::new();
 
         data.insert(Chroma, StatValues::from(&chroma));
@@ -103,117 +143,124 @@ impl ColorScheme {
         data
     }
 
+    /// Prints every target's value and contribution, grouped by priority if
+    /// the targets have different priorities.
     pub fn print_fitness(&self, descr: &ColorSchemeProblemDescription) {
-        let fixed_dist: Vec<(&Lab, &Lab, f32)> = descr.fixed_colors
-                                                      .iter()
-                                                      .flat_map(|col1| {
-                                                          self.free_colors
-                                                              .iter()
-                                                              .map(move |col2| {
-                                                                  (col1, col2, distance(col1, col2))
-                                                              })
-                                                      })
-                                                      .collect();
-
-        let free_dist: Vec<(&Lab, &Lab, f32)> = self.free_colors
-                                                    .iter()
-                                                    .enumerate()
-                                                    .flat_map(|(i, col1)| {
-                                                        self.free_colors
-                                                            .iter()
-                                                            .skip(i + 1)
-                                                            .map(move |col2| {
-                                                                (col1, col2, distance(col1, col2))
-                                                            })
-                                                    })
-                                                    .collect();
-
-        // for &coldist in fixed_dist.iter() {
-        //     print_col_dist(coldist);
-        // }
-
-        // for &coldist in free_dist.iter() {
-        //     print_col_dist(coldist);
-        // }
-
         let data = self.fitness_data(&descr);
-        for t in descr.fitness_targets.values() {
-            println!("{: <23} {: <6} {: <13} ( {:8.3} *{})^{} = {:11.3}",
-                     format!("{:?}",t.direction),
-                     format!("{:?}",t.stat),
-                     format!("{:?}", t.parameter),
-                     t.value(&data),
-                     t.strength.factor,
-                     t.strength.exponent,
-                     t.calculate(&data),
-                     );
+        let levels = descr.priority_levels();
+        for &level in levels.iter() {
+            let targets: Vec<&Target> = descr.fitness_targets
+                                             .values()
+                                             .filter(|t| t.priority == level)
+                                             .collect();
+            if levels.len() > 1 {
+                println!("priority {}: {:11.3}",
+                         level,
+                         targets.iter().map(|t| t.calculate(&data)).sum::<f32>());
+            }
+            for t in targets {
+                let status = match (t.satisfied(&data), t.required) {
+                    (Some(true), _) => "satisfied",
+                    (Some(false), false) => "violated",
+                    (Some(false), true) => "violated (required)",
+                    (None, _) => "",
+                };
+                let normalized = match descr.normalization {
+                    Normalization::None => String::new(),
+                    _ => {
+                        format!(" (raw {:11.3}, scale {:8.3})", t.calculate_raw(&data), t.scale)
+                    }
+                };
+                let value = match t.value(&data) {
+                    Some(value) => format!("{:8.3}", value),
+                    None => "   (none)".to_string(),
+                };
+                println!("{: <23} {: <20} ( {} *{})^{} {: <8} = {:11.3}{} {}",
+                         format!("{:?}",t.direction),
+                         t.name(),
+                         value,
+                         t.strength.factor,
+                         t.strength.exponent,
+                         t.strength.curve.to_string(),
+                         t.calculate(&data),
+                         normalized,
+                         status,
+                         );
+            }
+        }
+        if let Some(ref script) = descr.script {
+            match script.evaluate(&self.free_colors, &descr.fixed_colors) {
+                Ok(score) => {
+                    for &(ref name, value) in score.named.iter() {
+                        println!("{: <23} {: <20} = {:11.3}", "Script", name, value);
+                    }
+                    println!("{: <23} {: <20} = {:11.3}", "Script", "value", score.value);
+                }
+                Err(err) => println!("{}", err),
+            }
         }
     }
 }
 
 
-impl Genotype<ColorScheme, ColorSchemeProblemDescription> for ColorScheme {
-    fn rand<R: Rng>(descr: &ColorSchemeProblemDescription, rng: &mut R) -> ColorScheme {
-        let free_colors: Vec<Lab> = (0..descr.free_color_count)
-                                        .map(|_| {
-                                            Rgb::new(rng.gen_range(0.0, 1.0),
-                                                     rng.gen_range(0.0, 1.0),
-                                                     rng.gen_range(0.0, 1.0))
-                                                .into()
-                                        })
-                                        .collect();
-
-        ColorScheme { free_colors: free_colors, ..Default::default() }
+impl Genotype for ColorScheme {
+    /// Mean Euclidean Lab distance (CIE76 ΔE) between the colors of both
+    /// schemes, paired such that this is minimal. The order of the colors
+    /// does not matter.
+    fn distance(&self, other: &ColorScheme) -> f32 {
+        let n = self.free_colors.len().min(other.free_colors.len());
+        if n == 0 {
+            return 0.0;
+        }
+        let cost: Vec<Vec<f32>> = self.free_colors[..n]
+                                      .iter()
+                                      .map(|a| {
+                                          other.free_colors[..n]
+                                              .iter()
+                                              .map(|b| euclidean_distance(a, b))
+                                              .collect()
+                                      })
+                                      .collect();
+        min_cost_assignment(&cost)
+            .into_iter()
+            .enumerate()
+            .map(|(i, j)| cost[i][j])
+            .sum::<f32>() / n as f32
     }
+}
 
-    fn mutated<R: Rng>(&self, strength: f32, mut rng: &mut R) -> ColorScheme {
-        let normal_distribution = Normal::new(0.0, 0.02 * strength as f64);
-        let mut mutate = |x: f32, lower, upper| -> f32 {
-            let diff = normal_distribution.ind_sample(&mut rng) as f32;
-            (x + diff).min(upper).max(lower)
-        };
-
+/// Uniformly distributed in sRGB.
+pub fn random_scheme<R: Rng>(free_color_count: usize, rng: &mut R) -> ColorScheme {
+    let free_colors: Vec<Lab> = (0..free_color_count)
+                                    .map(|_| {
+                                        Rgb::new(rng.gen_range(0.0, 1.0),
+                                                 rng.gen_range(0.0, 1.0),
+                                                 rng.gen_range(0.0, 1.0))
+                                            .into()
+                                    })
+                                    .collect();
 
-        let mutated_free = self.free_colors
-                               .iter()
-                               .map(|old| {
-                                   let new = Lab::new(mutate(old.l, 0.0, 1.0),
-                                                      mutate(old.a, -1.0, 1.0),
-                                                      mutate(old.b, -1.0, 1.0));
-                                   assert!(new.l >= 0.0 && new.l <= 1.0);
-                                   assert!(new.a >= -1.0 && new.a <= 1.0);
-                                   assert!(new.b >= -1.0 && new.b <= 1.0);
-                                   new
-                               })
-                               .collect();
-
-        ColorScheme { free_colors: mutated_free, ..Default::default() }
-    }
+    ColorScheme::new(free_colors)
+}
 
-    fn crossover<R: Rng>(&self, other: &ColorScheme, rng: &mut R) -> ColorScheme {
-        let mut sorted_a = self.free_colors.clone();
-        sorted_a.sort_by_key(|&col| {
-            let lch: Lch = col.into();
-            (lch.hue.to_positive_degrees() * 100.0) as usize
-        });
-        let mut sorted_b = other.free_colors.clone();
-        sorted_b.sort_by_key(|&col| {
-            let lch: Lch = col.into();
-            (lch.hue.to_positive_degrees() * 100.0) as usize
-        });
-        let free = sorted_a.iter()
-            .zip(sorted_b.iter())
-            .map(|(a, b)| (*a + *b) / 2.0)
-            // .map(|(a, b)| if rng.gen::<bool>() {*a} else {*b})
-            .collect();
+pub type ColorSchemePopulation = Population<ColorSchemeProblemDescription,
+                                            SelectionStrategy,
+                                            CrossoverOperator,
+                                            MutationOperator>;
 
-        ColorScheme { free_colors: free, ..Default::default() }
-    }
+#[cfg(test)]
+mod test {
+    use super::*;
+    use genetic::Genotype;
+    use palette::Lab;
 
-    fn set_fitness(&mut self, fitness: f32) {
-        self.fitness = fitness;
-    }
-    fn get_fitness(&self) -> f32 {
-        self.fitness
+    #[test]
+    fn distance_ignores_order() {
+        let a = ColorScheme::new(vec![Lab::new(0.2, 0.1, 0.0), Lab::new(0.8, -0.3, 0.5)]);
+        let b = ColorScheme::new(vec![Lab::new(0.8, -0.3, 0.5), Lab::new(0.2, 0.1, 0.0)]);
+        assert_eq!(a.distance(&b), 0.0);
+        let c = ColorScheme::new(vec![Lab::new(0.8, -0.3, 0.5), Lab::new(0.2, 0.1, 0.2)]);
+        // b differs by 0.2 * 128 in one of two pairs
+        assert!((a.distance(&c) - 12.8).abs() < 1e-4);
     }
 }
@@ -0,0 +1,258 @@
+use palette::{Lab, Lch};
+use rand::Rng;
+use std::fmt;
+use std::str::FromStr;
+
+use assignment::min_cost_assignment;
+use color::euclidean_distance;
+use colorscheme::ColorScheme;
+use genetic::Crossover;
+
+/// How the colors of two parents are paired up before they are combined.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Matching {
+    /// The i-th color of one parent with the i-th color of the other. This
+    /// keeps every color in its position, which is its role when the free
+    /// colors are named.
+    Index,
+    /// Both parents sorted by hue. Colors close to the 0°/360° border are
+    /// paired with unrelated colors.
+    Hue,
+    /// The pairing with the smallest summed Lab distance. Both parents need
+    /// the same number of colors, as all schemes of a problem have.
+    Assignment,
+}
+
+/// How a pair of parent colors is turned into a child color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blend {
+    /// The mean of both colors.
+    Average,
+    /// Either color, with equal probability.
+    Uniform,
+    /// Uniformly from the box spanned by both colors in Lab, enlarged by
+    /// alpha times its size on every side (BLX-α).
+    Blx(f32),
+    /// Simulated binary crossover with distribution index eta: a large eta
+    /// creates children close to the parents.
+    Sbx(f32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrossoverOperator {
+    pub matching: Matching,
+    pub blend: Blend,
+}
+
+impl Default for CrossoverOperator {
+    fn default() -> CrossoverOperator {
+        CrossoverOperator {
+            matching: Matching::Hue,
+            blend: Blend::Average,
+        }
+    }
+}
+
+impl CrossoverOperator {
+    /// Swaps whole colors between parents without moving them, so every color
+    /// keeps its role.
+    pub fn role_preserving() -> CrossoverOperator {
+        CrossoverOperator {
+            matching: Matching::Index,
+            blend: Blend::Uniform,
+        }
+    }
+}
+
+impl FromStr for Matching {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Matching, String> {
+        match name {
+            "index" | "role" => Ok(Matching::Index),
+            "hue" => Ok(Matching::Hue),
+            "assignment" => Ok(Matching::Assignment),
+            _ => Err(format!("unknown matching {}, expected index, hue or assignment", name)),
+        }
+    }
+}
+
+impl FromStr for Blend {
+    type Err = String;
+
+    /// Parses `average`, `uniform`, `blx[:alpha]` or `sbx[:eta]`.
+    fn from_str(name: &str) -> Result<Blend, String> {
+        let mut parts = name.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let parameter = parts.next();
+        let parse = |default: f32| -> Result<f32, String> {
+            match parameter {
+                Some(p) => {
+                    p.parse()
+                     .ok()
+                     .and_then(|x: f32| if x >= 0.0 { Some(x) } else { None })
+                     .ok_or(format!("invalid parameter for crossover {}", name))
+                }
+                None => Ok(default),
+            }
+        };
+        match kind {
+            "average" => Ok(Blend::Average),
+            "uniform" => Ok(Blend::Uniform),
+            "blx" => Ok(Blend::Blx(try!(parse(0.5)))),
+            "sbx" => Ok(Blend::Sbx(try!(parse(2.0)))),
+            _ => Err(format!("unknown crossover {}, expected average, uniform, blx or sbx", name)),
+        }
+    }
+}
+
+impl fmt::Display for CrossoverOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let matching = match self.matching {
+            Matching::Index => "index",
+            Matching::Hue => "hue",
+            Matching::Assignment => "assignment",
+        };
+        match self.blend {
+            Blend::Average => write!(f, "{}/average", matching),
+            Blend::Uniform => write!(f, "{}/uniform", matching),
+            Blend::Blx(alpha) => write!(f, "{}/blx:{}", matching, alpha),
+            Blend::Sbx(eta) => write!(f, "{}/sbx:{}", matching, eta),
+        }
+    }
+}
+
+impl Crossover<ColorScheme> for CrossoverOperator {
+    fn crossover<R: Rng>(&self, a: &ColorScheme, b: &ColorScheme, rng: &mut R) -> ColorScheme {
+        let free = match_colors(self.matching, &a.free_colors, &b.free_colors)
+                       .into_iter()
+                       .map(|(x, y)| blend(self.blend, &x, &y, rng))
+                       .collect();
+        ColorScheme::new(free)
+    }
+}
+
+fn match_colors(matching: Matching, a: &[Lab], b: &[Lab]) -> Vec<(Lab, Lab)> {
+    match matching {
+        Matching::Index => a.iter().cloned().zip(b.iter().cloned()).collect(),
+        Matching::Hue => {
+            sorted_by_hue(a).into_iter().zip(sorted_by_hue(b).into_iter()).collect()
+        }
+        Matching::Assignment => {
+            let cost: Vec<Vec<f32>> = a.iter()
+                                       .map(|x| {
+                                           b.iter().map(|y| euclidean_distance(x, y)).collect()
+                                       })
+                                       .collect();
+            min_cost_assignment(&cost)
+                .into_iter()
+                .enumerate()
+                .map(|(i, j)| (a[i], b[j]))
+                .collect()
+        }
+    }
+}
+
+fn blend<R: Rng>(blend: Blend, x: &Lab, y: &Lab, rng: &mut R) -> Lab {
+    match blend {
+        Blend::Average => (*x + *y) / 2.0,
+        Blend::Uniform => {
+            if rng.gen::<bool>() {
+                *x
+            } else {
+                *y
+            }
+        }
+        Blend::Blx(alpha) => {
+            let mut component = |p: f32, q: f32, lower: f32, upper: f32| -> f32 {
+                let extent = alpha * (p - q).abs();
+                let low = (p.min(q) - extent).max(lower);
+                let high = (p.max(q) + extent).min(upper);
+                if low < high {
+                    rng.gen_range(low, high)
+                } else {
+                    low
+                }
+            };
+            Lab::new(component(x.l, y.l, 0.0, 1.0),
+                     component(x.a, y.a, -1.0, 1.0),
+                     component(x.b, y.b, -1.0, 1.0))
+        }
+        Blend::Sbx(eta) => {
+            let mut component = |p: f32, q: f32, lower: f32, upper: f32| -> f32 {
+                let u = rng.gen::<f32>();
+                let beta = if u <= 0.5 {
+                    (2.0 * u).powf(1.0 / (eta + 1.0))
+                } else {
+                    (1.0 / (2.0 * (1.0 - u))).powf(1.0 / (eta + 1.0))
+                };
+                let child = if rng.gen::<bool>() {
+                    0.5 * ((1.0 + beta) * p + (1.0 - beta) * q)
+                } else {
+                    0.5 * ((1.0 - beta) * p + (1.0 + beta) * q)
+                };
+                child.max(lower).min(upper)
+            };
+            Lab::new(component(x.l, y.l, 0.0, 1.0),
+                     component(x.a, y.a, -1.0, 1.0),
+                     component(x.b, y.b, -1.0, 1.0))
+        }
+    }
+}
+
+pub fn sorted_by_hue(colors: &[Lab]) -> Vec<Lab> {
+    let mut sorted = colors.to_vec();
+    sorted.sort_by_key(|&col| {
+        let lch: Lch = col.into();
+        (lch.hue.to_positive_degrees() * 100.0) as usize
+    });
+    sorted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use palette::Lab;
+    use rand::{SeedableRng, StdRng};
+    use colorscheme::ColorScheme;
+    use genetic::Crossover;
+
+    fn rng() -> StdRng {
+        let seed: &[usize] = &[3];
+        SeedableRng::from_seed(seed)
+    }
+
+    #[test]
+    fn assignment_pairs_closest_colors() {
+        let red = Lab::new(0.5, 0.6, 0.4);
+        let blue = Lab::new(0.3, 0.5, -0.8);
+        let pairs = match_colors(Matching::Assignment,
+                                 &[red, blue],
+                                 &[Lab::new(0.3, 0.5, -0.7), Lab::new(0.5, 0.6, 0.3)]);
+        assert_eq!(pairs[0].1.b, 0.3);
+        assert_eq!(pairs[1].1.b, -0.7);
+    }
+
+    #[test]
+    fn role_preserving_keeps_positions() {
+        let a = ColorScheme::new(vec![Lab::new(0.1, 0.0, 0.0), Lab::new(0.9, 0.0, 0.0)]);
+        let b = ColorScheme::new(vec![Lab::new(0.2, 0.0, 0.0), Lab::new(0.8, 0.0, 0.0)]);
+        let mut rng = rng();
+        for _ in 0..20 {
+            let child = CrossoverOperator::role_preserving().crossover(&a, &b, &mut rng);
+            assert!(child.free_colors[0].l < 0.5 && child.free_colors[1].l > 0.5);
+        }
+    }
+
+    #[test]
+    fn blx_stays_in_bounds() {
+        let x = Lab::new(0.95, -0.9, 0.2);
+        let y = Lab::new(0.99, -0.99, 0.3);
+        let mut rng = rng();
+        for _ in 0..100 {
+            let child = blend(Blend::Blx(0.5), &x, &y, &mut rng);
+            assert!(child.l >= 0.9 && child.l <= 1.0);
+            assert!(child.a >= -1.0 && child.a <= -0.855);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use palette::{Lab, Lch};
+use fitness::{ColorSchemeProblemDescription, Parameter, Stat};
+use colorscheme::ColorScheme;
+use color::*;
+use std::cmp::Ordering;
+use std::f32::NAN;
+
+/// A pair of colors, indexed into the fixed colors followed by the free colors.
+struct Pair {
+    a: usize,
+    b: usize,
+    distance: f32,
+    contrast: f32,
+}
+
+fn all_colors(scheme: &ColorScheme, descr: &ColorSchemeProblemDescription) -> Vec<Lab> {
+    descr.fixed_colors.iter().chain(scheme.free_colors.iter()).cloned().collect()
+}
+
+/// All fixed×free and free×free pairs, in the order in which `fitness_data`
+/// collects the distances.
+fn pairs(colors: &[Lab], fixed_count: usize) -> Vec<Pair> {
+    let free = fixed_count..colors.len();
+    let fixed_pairs = (0..fixed_count).flat_map(|a| free.clone().map(move |b| (a, b)));
+    let free_pairs = free.clone().flat_map(|a| (a + 1..colors.len()).map(move |b| (a, b)));
+    fixed_pairs.chain(free_pairs)
+               .map(|(a, b)| {
+                   Pair {
+                       a: a,
+                       b: b,
+                       distance: distance(&colors[a], &colors[b]),
+                       contrast: contrast_ratio(&colors[a], &colors[b]),
+                   }
+               })
+               .collect()
+}
+
+fn worst_pairs(pairs: &[Pair], k: usize) -> Vec<&Pair> {
+    let mut sorted: Vec<&Pair> = pairs.iter().collect();
+    sorted.sort_by(|p, q| p.distance.partial_cmp(&q.distance).unwrap_or(Ordering::Equal));
+    sorted.truncate(k);
+    sorted
+}
+
+/// The individual values a parameter's statistics are computed from, together
+/// with the colors (indexed like in `pairs`) each value was derived from.
+fn parameter_values(parameter: Parameter,
+                    colors: &[Lab],
+                    fixed_count: usize,
+                    pairs: &[Pair])
+                    -> Vec<(Vec<usize>, f32)> {
+    match parameter {
+        Parameter::Chroma => {
+            (fixed_count..colors.len())
+                .map(|i| {
+                    let lch: Lch = colors[i].into();
+                    (vec![i], lch.chroma * 128.0)
+                })
+                .collect()
+        }
+        Parameter::Luminance => {
+            (fixed_count..colors.len()).map(|i| (vec![i], colors[i].l * 100.0)).collect()
+        }
+        Parameter::FixedDistance => {
+            pairs.iter()
+                 .filter(|p| p.a < fixed_count)
+                 .map(|p| (vec![p.a, p.b], p.distance))
+                 .collect()
+        }
+        Parameter::FreeDistance => {
+            pairs.iter()
+                 .filter(|p| p.a >= fixed_count)
+                 .map(|p| (vec![p.a, p.b], p.distance))
+                 .collect()
+        }
+    }
+}
+
+fn print_cell(text: &str, highlighted: bool) {
+    if highlighted {
+        print!(" \x1b[1;7m{}\x1b[0m", text);
+    } else {
+        print!(" {}", text);
+    }
+}
+
+/// Prints the ΔE and contrast ratio of every fixed×free and free×free pair as
+/// a matrix, lists the `worst` closest pairs (highlighted in the matrix) and
+/// names the colors which determine the `Min`/`Max` targets.
+pub fn print_diagnostics(scheme: &ColorScheme,
+                         descr: &ColorSchemeProblemDescription,
+                         worst: usize) {
+    let fixed_count = descr.fixed_colors.len();
+    let colors = all_colors(scheme, descr);
+    let pairs = pairs(&colors, fixed_count);
+    let worst_pairs = worst_pairs(&pairs, worst);
+    let is_worst = |a: usize, b: usize| {
+        worst_pairs.iter().any(|p| (p.a == a && p.b == b) || (p.a == b && p.b == a))
+    };
+
+    println!("ΔE / contrast");
+    print!("   ");
+    for b in fixed_count..colors.len() {
+        print!("     ");
+        print_color(&colors[b]);
+        print!("     ");
+    }
+    println!("");
+    for a in 0..colors.len() {
+        print_color(&colors[a]);
+        for b in fixed_count..colors.len() {
+            if a == b {
+                print_cell("      -     ", false);
+                continue;
+            }
+            let pair = pairs.iter().find(|p| (p.a == a && p.b == b) || (p.a == b && p.b == a));
+            match pair {
+                Some(p) => {
+                    print_cell(&format!("{:5.1} {:4.1}:1", p.distance, p.contrast),
+                               is_worst(a, b))
+                }
+                None => print_cell("            ", false),
+            }
+        }
+        println!("");
+    }
+    println!("");
+
+    println!("closest pairs:");
+    for pair in worst_pairs.iter() {
+        print_col_dist((&colors[pair.a], &colors[pair.b], pair.distance));
+    }
+    println!("");
+
+    let data = scheme.fitness_data(descr);
+    for target in descr.fitness_targets.values() {
+        let (stat, parameter) = match target.expression.as_stat() {
+            Some(stat) => stat,
+            None => continue,
+        };
+        let values = parameter_values(parameter, &colors, fixed_count, &pairs);
+        let determining = match stat {
+            Stat::Min => values.iter().fold(None, |best: Option<&(Vec<usize>, f32)>, v| {
+                match best {
+                    Some(b) if b.1 <= v.1 => Some(b),
+                    _ => Some(v),
+                }
+            }),
+            Stat::Max => values.iter().fold(None, |best: Option<&(Vec<usize>, f32)>, v| {
+                match best {
+                    Some(b) if b.1 >= v.1 => Some(b),
+                    _ => Some(v),
+                }
+            }),
+            _ => continue,
+        };
+        print!("{: <6} {: <13} {:8.3} determined by ",
+               format!("{:?}", stat),
+               format!("{:?}", parameter),
+               target.value(&data).unwrap_or(NAN));
+        match determining {
+            Some(&(ref indices, _)) => {
+                for &i in indices.iter() {
+                    print_color(&colors[i]);
+                }
+                println!("");
+            }
+            None => println!("nothing (no values)"),
+        }
+    }
+    println!("");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use palette::Lab;
+
+    fn indices(pairs: &[&Pair]) -> Vec<(usize, usize)> {
+        pairs.iter().map(|p| (p.a, p.b)).collect()
+    }
+
+    #[test]
+    fn closest_pairs_first() {
+        // a fixed black and three free grays, two of them nearly equal
+        let colors = vec![Lab::new(0.0, 0.0, 0.0),
+                          Lab::new(0.5, 0.0, 0.0),
+                          Lab::new(0.52, 0.0, 0.0),
+                          Lab::new(0.9, 0.0, 0.0)];
+        let pairs = pairs(&colors, 1);
+        let all: Vec<&Pair> = pairs.iter().collect();
+        assert_eq!(indices(&all), vec![(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)]);
+        assert_eq!(indices(&worst_pairs(&pairs, 2)), vec![(1, 2), (2, 3)]);
+        assert_eq!(worst_pairs(&pairs, 10).len(), 6);
+
+        let fixed = parameter_values(Parameter::FixedDistance, &colors, 1, &pairs);
+        let fixed: Vec<Vec<usize>> = fixed.into_iter().map(|(i, _)| i).collect();
+        assert_eq!(fixed, vec![vec![0, 1], vec![0, 2], vec![0, 3]]);
+    }
+}
@@ -0,0 +1,186 @@
+use rand::Rng;
+use std::cmp::Ordering;
+use stats::{stddev, mean};
+
+use genetic::{Individual, Generation, ProblemDescription, diversity, evaluate,
+              evaluate_genotype};
+use optimizer::{Optimizer, ContinuousProblem, clamp, perturb};
+
+/// Differential evolution (DE/rand/1/bin). Every member competes with a trial
+/// vector built from three others and is replaced if the trial is at least
+/// as good by `Individual::compare`. The heat is ignored.
+pub struct DifferentialEvolution<P: ContinuousProblem> {
+    /// Scale of the difference vector, usually between 0.4 and 1.
+    pub differential_weight: f32,
+    /// Probability that a coordinate is taken from the mutant.
+    pub crossover_probability: f32,
+    points: Vec<Vec<f32>>,
+    individuals: Vec<Individual<P::Genotype>>,
+    problem_description: P,
+}
+
+impl<P: ContinuousProblem> DifferentialEvolution<P> {
+    /// The population has at least four members.
+    pub fn new<R: Rng>(size: usize,
+                       problem_description: P,
+                       rng: &mut R)
+                       -> DifferentialEvolution<P> {
+        let genotypes: Vec<P::Genotype> = (0..size.max(4))
+                                              .map(|_| problem_description.random_genotype(rng))
+                                              .collect();
+        let points = genotypes.iter().map(|g| problem_description.encode(g)).collect();
+        let individuals = genotypes.into_iter()
+                                   .map(|g| evaluate_genotype(&problem_description, g))
+                                   .collect();
+        DifferentialEvolution {
+            differential_weight: 0.5,
+            crossover_probability: 0.9,
+            points: points,
+            individuals: individuals,
+            problem_description: problem_description,
+        }
+    }
+}
+
+impl<P: ContinuousProblem> Optimizer for DifferentialEvolution<P> {
+    type Problem = P;
+
+    fn problem_description(&self) -> &P {
+        &self.problem_description
+    }
+
+    fn set_problem_description(&mut self, problem_description: P) {
+        for individual in self.individuals.iter_mut() {
+            evaluate(&problem_description, individual);
+        }
+        self.problem_description = problem_description;
+    }
+
+    fn update_problem<F: FnOnce(&mut P)>(&mut self, update: F) {
+        update(&mut self.problem_description);
+        for individual in self.individuals.iter_mut() {
+            evaluate(&self.problem_description, individual);
+        }
+    }
+
+    fn step<R: Rng>(&mut self, _heat: f32, rng: &mut R) -> Generation<P::Genotype> {
+        let size = self.points.len();
+        let n = self.points[0].len();
+        let bounds = self.problem_description.bounds();
+
+        let mut successes = 0;
+        let mut next_points = self.points.clone();
+        let mut next_individuals = self.individuals.clone();
+        for i in 0..size {
+            let mut others = vec![];
+            while others.len() < 3 {
+                let candidate = rng.gen_range(0, size);
+                if candidate != i && !others.contains(&candidate) {
+                    others.push(candidate);
+                }
+            }
+            let (a, b, c) = (&self.points[others[0]],
+                             &self.points[others[1]],
+                             &self.points[others[2]]);
+            let forced = rng.gen_range(0, n);
+            let mut trial: Vec<f32> = (0..n)
+                                          .map(|j| {
+                                              if j == forced ||
+                                                 rng.gen::<f32>() < self.crossover_probability {
+                                                  a[j] + self.differential_weight * (b[j] - c[j])
+                                              } else {
+                                                  self.points[i][j]
+                                              }
+                                          })
+                                          .collect();
+            clamp(&mut trial, &bounds);
+            let genotype = self.problem_description.decode(&trial);
+            let candidate = evaluate_genotype(&self.problem_description, genotype);
+            if candidate.compare(&self.individuals[i]) != Ordering::Greater {
+                successes += 1;
+                next_points[i] = trial;
+                next_individuals[i] = candidate;
+            }
+        }
+        self.points = next_points;
+        self.individuals = next_individuals;
+
+        let best = self.individuals
+                       .iter()
+                       .fold(&self.individuals[0], |best, i| {
+                           if i.compare(best) == Ordering::Less {
+                               i
+                           } else {
+                               best
+                           }
+                       })
+                       .clone();
+        Generation {
+            best: best,
+            mean_fitness: mean(self.individuals.iter().map(|i| i.fitness())) as f32,
+            sd_fitness: stddev(self.individuals.iter().map(|i| i.fitness())) as f32,
+            success_rate: Some(successes as f32 / size as f32),
+            reseeded: 0,
+            evaluated: self.individuals.clone(),
+        }
+    }
+
+    fn diversity(&self) -> f32 {
+        diversity(&self.individuals)
+    }
+
+    fn seed<R: Rng>(&mut self, genotypes: &[P::Genotype], fraction: f32, rng: &mut R) {
+        if genotypes.is_empty() {
+            return;
+        }
+        let size = self.points.len();
+        let bounds = self.problem_description.bounds();
+        let count = ((fraction * size as f32).ceil() as usize).max(genotypes.len()).min(size);
+        for i in 0..count {
+            let seed = self.problem_description.encode(&genotypes[i % genotypes.len()]);
+            let point = if i < genotypes.len() {
+                seed
+            } else {
+                perturb(&seed, &bounds, 0.02, rng)
+            };
+            let genotype = self.problem_description.decode(&point);
+            self.points[i] = point;
+            self.individuals[i] = evaluate_genotype(&self.problem_description, genotype);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use optimizer::Optimizer;
+    use optimizer::test::{Sphere, HalfSphere};
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn converges_on_sphere() {
+        let seed: &[usize] = &[12];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut de = DifferentialEvolution::new(20, Sphere(4), &mut rng);
+        let mut best = ::std::f32::MIN;
+        for _ in 0..300 {
+            best = de.step(1.0, &mut rng).best.fitness();
+        }
+        assert!(best > -1e-3, "best fitness {}", best);
+    }
+
+    #[test]
+    fn respects_constraints() {
+        let seed: &[usize] = &[12];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let mut de = DifferentialEvolution::new(20, HalfSphere(4), &mut rng);
+        let mut best = None;
+        for _ in 0..300 {
+            best = Some(de.step(1.0, &mut rng).best);
+        }
+        let best = best.unwrap();
+        // the unconstrained optimum has x[0] = 0.3
+        assert!(best.is_feasible(), "violation {}", best.violation());
+        assert!(best.genotype.0[0] > -0.05, "x[0] = {}", best.genotype.0[0]);
+    }
+}
@@ -0,0 +1,118 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong between reading the arguments and writing
+/// the results, with the context it occurred in.
+#[derive(Debug)]
+pub enum Error {
+    /// Invalid command line arguments.
+    Usage(String),
+    /// Input which could not be parsed, like a target or a palette.
+    Parse {
+        context: String,
+        message: String,
+    },
+    /// A problem which can't be optimized, see
+    /// `ColorSchemeProblemDescription::validate`.
+    InvalidProblem(String),
+    Io {
+        context: String,
+        error: io::Error,
+    },
+    /// Writing the scheme to the given path failed.
+    Export {
+        path: String,
+        error: io::Error,
+    },
+}
+
+impl Error {
+    pub fn parse<C: Into<String>, M: Into<String>>(context: C, message: M) -> Error {
+        Error::Parse {
+            context: context.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn io<C: Into<String>>(context: C, error: io::Error) -> Error {
+        Error::Io {
+            context: context.into(),
+            error: error,
+        }
+    }
+
+    /// Replaces the context of a parse or I/O error by one the caller knows
+    /// better, like the line a target was read from.
+    pub fn with_context<C: Into<String>>(self, context: C) -> Error {
+        match self {
+            Error::Parse { message, .. } => Error::parse(context, message),
+            Error::Io { error, .. } => Error::io(context, error),
+            err => err,
+        }
+    }
+
+    /// The exit code of the binary, as in BSD's sysexits.h.
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::Usage(_) => 64,
+            Error::Parse { .. } | Error::InvalidProblem(_) => 65,
+            Error::Export { .. } => 73,
+            Error::Io { .. } => 74,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Usage(ref message) => write!(f, "{}", message),
+            Error::Parse { ref context, ref message } => write!(f, "{}: {}", context, message),
+            Error::InvalidProblem(ref message) => write!(f, "invalid problem: {}", message),
+            Error::Io { ref context, ref error } => write!(f, "{}: {}", context, error),
+            Error::Export { ref path, ref error } => {
+                write!(f, "could not export to {}: {}", path, error)
+            }
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn description(&self) -> &str {
+        match *self {
+            Error::Usage(_) => "invalid arguments",
+            Error::Parse { .. } => "invalid input",
+            Error::InvalidProblem(_) => "invalid problem",
+            Error::Io { ref error, .. } |
+            Error::Export { ref error, .. } => error::Error::description(error),
+        }
+    }
+
+    fn cause(&self) -> Option<&error::Error> {
+        match *self {
+            Error::Io { ref error, .. } |
+            Error::Export { ref error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn context_and_exit_code() {
+        let err = Error::parse("line 3", "expected string");
+        assert_eq!(err.to_string(), "line 3: expected string");
+        assert_eq!(err.exit_code(), 65);
+        assert_eq!(err.with_context("line 4").to_string(), "line 4: expected string");
+        let err = Error::Export {
+            path: "scheme.css".to_string(),
+            error: io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+        };
+        assert_eq!(err.to_string(), "could not export to scheme.css: denied");
+        assert_eq!(err.exit_code(), 73);
+    }
+}
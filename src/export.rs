@@ -0,0 +1,104 @@
+use palette::{Lab, Rgb};
+use palette::pixel::Srgb;
+use palette::Limited;
+use std::io;
+use std::io::Write;
+
+use fitness::ColorSchemeProblemDescription;
+use colorscheme::ColorScheme;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// One `#rrggbb` per line, fixed colors first.
+    Hex,
+    /// Custom properties `--fixed-N` and `--color-N` (or the role names) on `:root`.
+    Css,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Result<ExportFormat, String> {
+        match name {
+            "hex" => Ok(ExportFormat::Hex),
+            "css" => Ok(ExportFormat::Css),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(format!("unknown export format {}, expected hex, css or json", name)),
+        }
+    }
+
+    /// Guesses the format from the file extension, defaulting to hex.
+    pub fn from_path(path: &str) -> ExportFormat {
+        if path.ends_with(".css") {
+            ExportFormat::Css
+        } else if path.ends_with(".json") {
+            ExportFormat::Json
+        } else {
+            ExportFormat::Hex
+        }
+    }
+}
+
+/// `#rrggbb` of the color clamped to sRGB.
+pub fn hex(color: &Lab) -> String {
+    let mut rgb: Rgb = (*color).into();
+    rgb.clamp_self();
+    let srgb = Srgb::from_linear(rgb);
+    format!("#{:02x}{:02x}{:02x}",
+            (srgb.red * 255.0).round() as u8,
+            (srgb.green * 255.0).round() as u8,
+            (srgb.blue * 255.0).round() as u8)
+}
+
+/// Writes the fixed and free colors of a scheme. The free colors are written
+/// in the same order as they are previewed.
+pub fn export<W: Write>(scheme: &ColorScheme,
+                        descr: &ColorSchemeProblemDescription,
+                        format: ExportFormat,
+                        out: &mut W)
+                        -> io::Result<()> {
+    let fixed: Vec<String> = descr.fixed_colors.iter().map(hex).collect();
+    let free: Vec<String> = scheme.display_colors(descr).iter().map(hex).collect();
+    match format {
+        ExportFormat::Hex => {
+            for color in fixed.iter().chain(free.iter()) {
+                try!(writeln!(out, "{}", color));
+            }
+        }
+        ExportFormat::Css => {
+            try!(writeln!(out, ":root {{"));
+            for (i, color) in fixed.iter().enumerate() {
+                try!(writeln!(out, "  --fixed-{}: {};", i, color));
+            }
+            for (i, color) in free.iter().enumerate() {
+                match descr.roles.get(i) {
+                    Some(role) => try!(writeln!(out, "  --{}: {};", role, color)),
+                    None => try!(writeln!(out, "  --color-{}: {};", i, color)),
+                }
+            }
+            try!(writeln!(out, "}}"));
+        }
+        ExportFormat::Json => {
+            let quoted = |strings: &[String]| -> String {
+                strings.iter().map(|c| format!("\"{}\"", c)).collect::<Vec<_>>().join(", ")
+            };
+            try!(writeln!(out,
+                          "{{\"fixed\": [{}], \"free\": [{}], \"roles\": [{}]}}",
+                          quoted(&fixed),
+                          quoted(&free),
+                          quoted(&descr.roles)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use palette::Lab;
+
+    #[test]
+    fn hex_black_white() {
+        assert_eq!(hex(&Lab::new(0.0, 0.0, 0.0)), "#000000");
+        assert_eq!(hex(&Lab::new(1.0, 0.0, 0.0)), "#ffffff");
+    }
+}
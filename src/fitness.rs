@@ -1,28 +1,259 @@
 use palette::{Lab, Lch};
+use std::cell::RefCell;
 use std::collections::HashMap;
 use stats::{stddev, mean};
 use std::f32::MAX;
 use std::f32::MIN;
+use std::f32::NAN;
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
 use genetic::ProblemDescription;
-use colorscheme::ColorScheme;
+use optimizer::ContinuousProblem;
+use colorscheme::{ColorScheme, random_scheme};
+use cache::FitnessCache;
+use script::FitnessScript;
+use error::Error;
+use rand::Rng;
 
 #[derive(Clone)]
 pub struct ColorSchemeProblemDescription {
     pub free_color_count: usize,
     pub fixed_colors: Vec<Lab>,
-    pub fitness_targets: HashMap<(Stat, Parameter), Target>,
+    /// By the name of the targets, so a target replaces the one on the same
+    /// statistic or expression.
+    pub fitness_targets: HashMap<String, Target>,
+    /// Optional names of the free colors, e.g. `keyword` or `comment`. The
+    /// position of a free color is its role.
+    pub roles: Vec<String>,
+    /// Shared by all evaluations, which only take the problem by reference.
+    pub cache: RefCell<FitnessCache>,
+    /// How the scales of the targets are determined, see `normalize`.
+    pub normalization: Normalization,
+    /// The width of the buckets priority levels are compared in, see
+    /// `Individual::compare`.
+    pub priority_tolerance: f32,
+    /// Its score is added to the fitness, at the highest priority.
+    pub script: Option<FitnessScript>,
 }
 
 impl ColorSchemeProblemDescription {
+    pub fn new(free_color_count: usize, fixed_colors: Vec<Lab>) -> ColorSchemeProblemDescription {
+        ColorSchemeProblemDescription {
+            free_color_count: free_color_count,
+            fixed_colors: fixed_colors,
+            fitness_targets: HashMap::new(),
+            roles: vec![],
+            cache: RefCell::new(FitnessCache::new()),
+            normalization: Normalization::None,
+            priority_tolerance: 0.0,
+            script: None,
+        }
+    }
+
+    /// Names the free colors, one per role.
+    pub fn set_roles(&mut self, roles: Vec<String>) {
+        self.free_color_count = roles.len();
+        self.roles = roles;
+    }
+
+    /// Sets the scale of every target to the range of its value over the
+    /// schemes, so that the factors become relative weights. Targets whose
+    /// value does not vary get a scale of 1.
+    pub fn normalize(&mut self, schemes: &[&ColorScheme]) {
+        let data: Vec<FitnessData> = schemes.iter().map(|s| s.fitness_data(self)).collect();
+        for target in self.fitness_targets.values_mut() {
+            let (min, max) = data.iter()
+                                 .filter_map(|d| target.value(d))
+                                 .filter(|v| v.is_finite())
+                                 .fold((MAX, MIN), |(min, max), v| (min.min(v), max.max(v)));
+            let range = max - min;
+            target.scale = if range > 0.0 { range } else { 1.0 };
+        }
+    }
+
+    /// The scale of every target by its name.
+    pub fn scales(&self) -> HashMap<String, f32> {
+        self.fitness_targets.iter().map(|(name, target)| (name.clone(), target.scale)).collect()
+    }
+
+    /// Sets the scales of the targets with the given names.
+    pub fn set_scales(&mut self, scales: &HashMap<String, f32>) {
+        for (name, target) in self.fitness_targets.iter_mut() {
+            if let Some(&scale) = scales.get(name) {
+                target.scale = scale;
+            }
+        }
+    }
+
+    /// Normalizes by the given number of random schemes.
+    pub fn calibrate<R: Rng>(&mut self, samples: usize, rng: &mut R) {
+        let schemes: Vec<ColorScheme> = (0..samples).map(|_| self.random_genotype(rng)).collect();
+        let schemes: Vec<&ColorScheme> = schemes.iter().collect();
+        self.normalize(&schemes);
+    }
+
+    /// The distinct priorities of the targets, most important first.
+    pub fn priority_levels(&self) -> Vec<usize> {
+        let mut levels: Vec<usize> = self.fitness_targets.values().map(|t| t.priority).collect();
+        levels.sort();
+        levels.dedup();
+        levels
+    }
+
+    fn script_score(&self, scheme: &ColorScheme) -> f32 {
+        match self.script {
+            Some(ref script) => script.score(&scheme.free_colors, &self.fixed_colors),
+            None => 0.0,
+        }
+    }
+
+    /// The distance between two colors, as memoized by the cache.
+    pub fn distance(&self, col1: &Lab, col2: &Lab) -> f32 {
+        self.cache.borrow_mut().distance(col1, col2)
+    }
+
+    /// Checks that the problem has free colors and that no target is
+    /// undefined for every scheme, like the distances between free colors of
+    /// a scheme with one free color.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.free_color_count == 0 {
+            return Err(Error::InvalidProblem("the problem has no free colors".to_string()));
+        }
+        for target in self.fitness_targets.values() {
+            try!(self.check_target(target));
+        }
+        Ok(())
+    }
+
+    /// Whether the target is defined for the schemes of this problem.
+    pub fn check_target(&self, target: &Target) -> Result<(), Error> {
+        let n = self.free_color_count;
+        let count = |parameter: Parameter| {
+            match parameter {
+                Parameter::Chroma | Parameter::Luminance => n,
+                Parameter::FixedDistance => n * self.fixed_colors.len(),
+                Parameter::FreeDistance => n * n.saturating_sub(1) / 2,
+            }
+        };
+        let message = match target.expression.undefined_stat(&count) {
+            Some((_, Parameter::FreeDistance)) => {
+                format!("{} needs at least 2 free colors, there is {}",
+                        target.name(),
+                        self.free_color_count)
+            }
+            Some((_, Parameter::FixedDistance)) if self.fixed_colors.is_empty() => {
+                format!("{} needs fixed colors", target.name())
+            }
+            Some(_) => format!("{} needs free colors", target.name()),
+            None => return Ok(()),
+        };
+        Err(Error::InvalidProblem(message))
+    }
+
+    /// The targets whose contribution to the fitness of the scheme is NaN,
+    /// and `script` if the script's score is.
+    pub fn nan_targets(&self, scheme: &ColorScheme) -> Vec<String> {
+        let data = scheme.fitness_data(&self);
+        let mut names: Vec<String> = self.fitness_targets
+                                         .values()
+                                         .filter(|target| target.calculate(&data).is_nan())
+                                         .map(|target| target.name())
+                                         .collect();
+        names.sort();
+        if self.script_score(scheme).is_nan() {
+            names.push("script".to_string());
+        }
+        names
+    }
+
     pub fn set(&mut self, target: Target) {
-        self.fitness_targets.insert((target.stat, target.parameter), target);
+        self.fitness_targets.insert(target.name(), target);
     }
 }
 
-impl ProblemDescription<ColorScheme, ColorSchemeProblemDescription> for ColorSchemeProblemDescription {
+impl ProblemDescription for ColorSchemeProblemDescription {
+    type Genotype = ColorScheme;
+
+    fn random_genotype<R: Rng>(&self, rng: &mut R) -> ColorScheme {
+        random_scheme(self.free_color_count, rng)
+    }
+
+    /// Required targets are included, so optimizers which ignore the
+    /// constraints treat them as penalties.
     fn calculate_fitness(&self, scheme: &ColorScheme) -> f32 {
         let data = scheme.fitness_data(&self);
-        self.fitness_targets.values().map(|target| target.calculate(&data)).sum::<f32>()
+        self.fitness_targets.values().map(|target| target.calculate(&data)).sum::<f32>() +
+        self.script_score(scheme)
+    }
+
+    /// The summed violations of the required targets, each divided by its
+    /// scale. The strength only shapes the penalty in the fitness, so even a
+    /// negative factor can't make a violation negative.
+    fn constraint_violation(&self, scheme: &ColorScheme) -> f32 {
+        if !self.fitness_targets.values().any(|target| target.required) {
+            return 0.0;
+        }
+        let data = scheme.fitness_data(&self);
+        self.fitness_targets
+            .values()
+            .filter(|target| target.required)
+            .map(|target| {
+                target.value(&data)
+                      .and_then(|value| target.violation(value))
+                      .map_or(0.0, |violation| violation / target.scale)
+            })
+            .sum::<f32>()
+    }
+
+    /// The summed contributions of the targets of every priority level.
+    fn priority_fitness(&self, scheme: &ColorScheme) -> Vec<f32> {
+        let levels = self.priority_levels();
+        if levels.len() < 2 {
+            return vec![];
+        }
+        let data = scheme.fitness_data(&self);
+        let mut fitness: Vec<f32> = levels.iter()
+                                          .map(|&level| {
+                                              self.fitness_targets
+                                                  .values()
+                                                  .filter(|target| target.priority == level)
+                                                  .map(|target| target.calculate(&data))
+                                                  .sum::<f32>()
+                                          })
+                                          .collect();
+        fitness[0] += self.script_score(scheme);
+        fitness
+    }
+
+    fn priority_tolerance(&self) -> f32 {
+        self.priority_tolerance
+    }
+}
+
+/// L, a and b of every free color.
+impl ContinuousProblem for ColorSchemeProblemDescription {
+    fn bounds(&self) -> Vec<(f32, f32)> {
+        (0..self.free_color_count)
+            .flat_map(|_| vec![(0.0, 1.0), (-1.0, 1.0), (-1.0, 1.0)])
+            .collect()
+    }
+
+    fn encode(&self, scheme: &ColorScheme) -> Vec<f32> {
+        scheme.free_colors.iter().flat_map(|c| vec![c.l, c.a, c.b]).collect()
+    }
+
+    fn decode(&self, x: &[f32]) -> ColorScheme {
+        let clamp = |v: f32, lower: f32, upper: f32| v.max(lower).min(upper);
+        ColorScheme::new(x.chunks(3)
+                          .map(|c| {
+                              Lab::new(clamp(c[0], 0.0, 1.0),
+                                       clamp(c[1], -1.0, 1.0),
+                                       clamp(c[2], -1.0, 1.0))
+                          })
+                          .collect())
     }
 }
 
@@ -34,22 +265,215 @@ pub enum Parameter {
     FreeDistance,
 }
 
+impl Parameter {
+    /// The name in targets and expressions.
+    pub fn keyword(&self) -> &'static str {
+        match *self {
+            Parameter::Chroma => "chroma",
+            Parameter::Luminance => "luminance",
+            Parameter::FixedDistance => "fixeddist",
+            Parameter::FreeDistance => "freedist",
+        }
+    }
+}
+
 pub type FitnessData = HashMap<Parameter, StatValues>;
 
+/// A value computed from the statistics of a scheme's parameters.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expression {
+    Number(f32),
+    Stat(Stat, Parameter),
+    Negate(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+    Function(Function, Vec<Expression>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+/// Functions of numbers. The statistics of parameters are `Expression::Stat`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Function {
+    Abs,
+    Sqrt,
+    Ln,
+    Exp,
+    Min,
+    Max,
+}
+
+impl Expression {
+    /// `None` if a statistic is undefined, see `StatValues::get`, or its
+    /// parameter is missing.
+    pub fn evaluate(&self, data: &FitnessData) -> Option<f32> {
+        match *self {
+            Expression::Number(x) => Some(x),
+            Expression::Stat(ref stat, ref parameter) => {
+                data.get(parameter).and_then(|values| values.get(stat))
+            }
+            Expression::Negate(ref e) => e.evaluate(data).map(|x| -x),
+            Expression::Binary(operator, ref a, ref b) => {
+                let (a, b) = match (a.evaluate(data), b.evaluate(data)) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => return None,
+                };
+                Some(match operator {
+                    Operator::Add => a + b,
+                    Operator::Subtract => a - b,
+                    Operator::Multiply => a * b,
+                    Operator::Divide => a / b,
+                    Operator::Power => a.powf(b),
+                })
+            }
+            Expression::Function(function, ref arguments) => {
+                let values: Vec<f32> = match arguments.iter().map(|a| a.evaluate(data)).collect() {
+                    Some(values) => values,
+                    None => return None,
+                };
+                Some(match function {
+                    Function::Abs => values[0].abs(),
+                    Function::Sqrt => values[0].sqrt(),
+                    Function::Ln => values[0].ln(),
+                    Function::Exp => values[0].exp(),
+                    Function::Min => values.iter().fold(MAX, |min, &x| min.min(x)),
+                    Function::Max => values.iter().fold(MIN, |max, &x| max.max(x)),
+                })
+            }
+        }
+    }
+
+    /// A statistic which is undefined for the given number of values of each
+    /// parameter.
+    fn undefined_stat<F: Fn(Parameter) -> usize>(&self, count: &F) -> Option<(Stat, Parameter)> {
+        match *self {
+            Expression::Number(_) => None,
+            Expression::Stat(stat, parameter) => {
+                if stat.is_defined(count(parameter)) {
+                    None
+                } else {
+                    Some((stat, parameter))
+                }
+            }
+            Expression::Negate(ref e) => e.undefined_stat(count),
+            Expression::Binary(_, ref a, ref b) => {
+                a.undefined_stat(count).or_else(|| b.undefined_stat(count))
+            }
+            Expression::Function(_, ref arguments) => {
+                arguments.iter().filter_map(|a| a.undefined_stat(count)).next()
+            }
+        }
+    }
+
+    pub fn as_stat(&self) -> Option<(Stat, Parameter)> {
+        match *self {
+            Expression::Stat(stat, parameter) => Some((stat, parameter)),
+            _ => None,
+        }
+    }
+
+    fn precedence(&self) -> usize {
+        match *self {
+            Expression::Binary(Operator::Add, _, _) |
+            Expression::Binary(Operator::Subtract, _, _) => 1,
+            Expression::Binary(Operator::Multiply, _, _) |
+            Expression::Binary(Operator::Divide, _, _) => 2,
+            Expression::Negate(_) => 3,
+            Expression::Binary(Operator::Power, _, _) => 4,
+            _ => 5,
+        }
+    }
+}
+
+impl fmt::Display for Expression {
+    /// In the syntax of `parse::parse_expression`, with only the necessary
+    /// parentheses.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let precedence = self.precedence();
+        let operand = |e: &Expression, parenthesize: bool| {
+            if parenthesize {
+                format!("({})", e)
+            } else {
+                format!("{}", e)
+            }
+        };
+        match *self {
+            Expression::Number(x) => write!(f, "{}", x),
+            Expression::Stat(stat, parameter) => {
+                let keyword = parameter.keyword();
+                match stat {
+                    Stat::Percentile(p) => write!(f, "percentile({}, {})", keyword, p),
+                    Stat::CountBelow(t) => write!(f, "countbelow({}, {})", keyword, t),
+                    Stat::SoftMin(t) => write!(f, "softmin({}, {})", keyword, t),
+                    Stat::SoftMax(t) => write!(f, "softmax({}, {})", keyword, t),
+                    _ => write!(f, "{}({})", format!("{:?}", stat).to_lowercase(), keyword),
+                }
+            }
+            Expression::Negate(ref e) => write!(f, "-{}", operand(e, e.precedence() < precedence)),
+            Expression::Binary(operator, ref a, ref b) => {
+                let symbol = match operator {
+                    Operator::Add => "+",
+                    Operator::Subtract => "-",
+                    Operator::Multiply => "*",
+                    Operator::Divide => "/",
+                    Operator::Power => "^",
+                };
+                // all operators but the power are left associative
+                let right_associative = operator == Operator::Power;
+                write!(f,
+                       "{} {} {}",
+                       operand(a,
+                               a.precedence() < precedence ||
+                               (right_associative && a.precedence() == precedence)),
+                       symbol,
+                       operand(b,
+                               b.precedence() < precedence ||
+                               (!right_associative && b.precedence() == precedence)))
+            }
+            Expression::Function(function, ref arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                write!(f,
+                       "{}({})",
+                       format!("{:?}", function).to_lowercase(),
+                       arguments.join(", "))
+            }
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct Target {
     pub direction: TargetDirection,
-    pub stat: Stat,
-    pub parameter: Parameter,
+    /// A single statistic of a parameter for targets like `min freedist`.
+    pub expression: Expression,
     pub strength: Strength,
+    /// A hard constraint: schemes violating it lose against all which don't.
+    pub required: bool,
+    /// The value (or violation) is divided by this before the strength is
+    /// applied. 1 unless the problem is normalized.
+    pub scale: f32,
+    /// Targets of a lower priority only break ties between schemes which are
+    /// equally fit in the targets of higher priorities. 1 is the highest.
+    pub priority: usize,
 }
 
 #[derive(Clone, Debug)]
 pub enum TargetDirection {
     Maximize,
     Minimize,
-    Approximate(f32),
+    /// Value and tolerance: deviations within the tolerance are not penalized.
+    Approximate(f32, f32),
+    /// Only values below the threshold are penalized.
+    AtLeast(f32),
+    AtMost(f32),
+    /// Only values outside the band are penalized.
+    Between(f32, f32),
 }
 
 impl Target {
@@ -58,50 +482,300 @@ impl Target {
                parameter: Parameter,
                strength: Strength)
                -> Target {
+        Target::from_expression(direction, Expression::Stat(stat, parameter), strength)
+    }
+
+    pub fn from_expression(direction: TargetDirection,
+                           expression: Expression,
+                           strength: Strength)
+                           -> Target {
         Target {
             direction: direction,
-            stat: stat,
-            parameter: parameter,
+            expression: expression,
             strength: strength,
+            required: false,
+            scale: 1.0,
+            priority: 1,
         }
     }
 }
 
 impl Target {
-    pub fn value(&self, data: &FitnessData) -> f32 {
-        data.get(&self.parameter).unwrap().get(&self.stat)
+    /// Like `Min FreeDistance` for a single statistic, the expression
+    /// otherwise.
+    pub fn name(&self) -> String {
+        match self.expression.as_stat() {
+            Some((stat, parameter)) => format!("{:?} {:?}", stat, parameter),
+            None => self.expression.to_string(),
+        }
     }
+
+    /// `None` if the value is undefined, e.g. the minimum of no distances.
+    pub fn value(&self, data: &FitnessData) -> Option<f32> {
+        self.expression.evaluate(data)
+    }
+
+    /// How far the value is from satisfying a threshold direction, 0 if it
+    /// does. `None` for `Maximize` and `Minimize`, which are never satisfied.
+    pub fn violation(&self, value: f32) -> Option<f32> {
+        match self.direction {
+            TargetDirection::Maximize | TargetDirection::Minimize => None,
+            TargetDirection::Approximate(against, tolerance) => {
+                Some(((against - value).abs() - tolerance).max(0.0))
+            }
+            TargetDirection::AtLeast(threshold) => Some((threshold - value).max(0.0)),
+            TargetDirection::AtMost(threshold) => Some((value - threshold).max(0.0)),
+            TargetDirection::Between(lower, upper) => {
+                Some((lower - value).max(0.0) + (value - upper).max(0.0))
+            }
+        }
+    }
+
+    pub fn satisfied(&self, data: &FitnessData) -> Option<bool> {
+        self.value(data)
+            .and_then(|value| self.violation(value))
+            .map(|violation| violation == 0.0)
+    }
+
     pub fn calculate(&self, data: &FitnessData) -> f32 {
-        let value = self.value(&data);
+        self.contribution(data, self.scale)
+    }
+
+    /// The contribution without normalization.
+    pub fn calculate_raw(&self, data: &FitnessData) -> f32 {
+        self.contribution(data, 1.0)
+    }
+
+    /// Undefined values contribute nothing.
+    fn contribution(&self, data: &FitnessData, scale: f32) -> f32 {
+        let value = match self.value(&data) {
+            Some(value) => value,
+            None => return 0.0,
+        };
         match self.direction {
-            TargetDirection::Maximize => self.strength.calculate(value),
-            TargetDirection::Minimize => -self.strength.calculate(value),
-            TargetDirection::Approximate(against) => {
-                -self.strength.calculate((against - value).abs())
+            TargetDirection::Maximize => self.strength.calculate(value / scale),
+            TargetDirection::Minimize => -self.strength.calculate(value / scale),
+            _ => {
+                match self.violation(value) {
+                    Some(violation) if violation > 0.0 => {
+                        -self.strength.calculate(violation / scale)
+                    }
+                    _ => 0.0,
+                }
+            }
+        }
+
+    }
+}
+
+/// How the scales of the targets are chosen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Normalization {
+    None,
+    /// By the range of each target in the current population, updated every
+    /// generation. The best fitness of different generations is then no
+    /// longer comparable.
+    Population,
+    /// By the range of each target over the given number of random schemes,
+    /// whenever the targets change.
+    Calibration(usize),
+}
+
+impl Default for Normalization {
+    fn default() -> Normalization {
+        Normalization::None
+    }
+}
+
+impl FromStr for Normalization {
+    type Err = String;
+
+    /// Parses `none`, `population` or `calibration[:SAMPLES]`.
+    fn from_str(name: &str) -> Result<Normalization, String> {
+        let mut parts = name.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        match (kind, parts.next()) {
+            ("none", None) => Ok(Normalization::None),
+            ("population", None) => Ok(Normalization::Population),
+            ("calibration", None) => Ok(Normalization::Calibration(1000)),
+            ("calibration", Some(samples)) => {
+                match samples.parse() {
+                    Ok(samples) if samples >= 2 => Ok(Normalization::Calibration(samples)),
+                    _ => Err(format!("expected at least 2 calibration samples in {}", name)),
+                }
+            }
+            _ => {
+                Err(format!("unknown normalization {}, expected none, population or \
+                             calibration[:SAMPLES]",
+                            name))
             }
         }
+    }
+}
 
+impl fmt::Display for Normalization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Normalization::None => write!(f, "none"),
+            Normalization::Population => write!(f, "population"),
+            Normalization::Calibration(samples) => write!(f, "calibration:{}", samples),
+        }
     }
 }
 
 #[derive(Clone)]
 pub struct Strength {
     pub factor: f32,
-    pub exponent: i32,
+    pub exponent: f32,
+    pub curve: Curve,
 }
 
 impl Strength {
     fn calculate(&self, value: f32) -> f32 {
-        (self.factor * value).powi(self.exponent)
+        let x = self.factor * value;
+        match self.curve {
+            Curve::Power if self.exponent.fract() == 0.0 => x.powi(self.exponent as i32),
+            Curve::Power => x.powf(self.exponent),
+            Curve::SignedPower => x.signum() * x.abs().powf(self.exponent),
+            Curve::Log => x.signum() * x.abs().ln_1p(),
+            Curve::Logistic(midpoint, width) => {
+                self.factor / (1.0 + (-(value - midpoint) / width).exp())
+            }
+            Curve::Huber(delta) => {
+                let huber = if x.abs() <= delta {
+                    x * x / 2.0
+                } else {
+                    delta * (x.abs() - delta / 2.0)
+                };
+                x.signum() * huber
+            }
+        }
+    }
+}
+
+/// How a target's value is turned into fitness. All but `Logistic` scale the
+/// value by the factor first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Curve {
+    /// `(factor * value)^exponent`. Integer exponents keep the sign of even
+    /// powers positive, fractional ones are undefined for negative values.
+    Power,
+    /// Like `Power`, but keeps the sign of the value for every exponent.
+    SignedPower,
+    /// `ln(1 + factor * value)`, signed. Grows slowly for large values.
+    Log,
+    /// Midpoint and width: rises from 0 to the factor around the midpoint,
+    /// so very large values stop mattering.
+    Logistic(f32, f32),
+    /// Quadratic up to the given value, linear beyond it, signed.
+    Huber(f32),
+}
+
+impl Default for Curve {
+    fn default() -> Curve {
+        Curve::Power
+    }
+}
+
+impl FromStr for Curve {
+    type Err = String;
+
+    /// Parses `power`, `signed`, `log`, `logistic:MIDPOINT:WIDTH` or
+    /// `huber:DELTA`.
+    fn from_str(name: &str) -> Result<Curve, String> {
+        let parts: Vec<&str> = name.split(':').collect();
+        let numbers: Vec<f32> = try!(parts[1..]
+                                         .iter()
+                                         .map(|p| p.trim().parse())
+                                         .collect::<Result<_, _>>()
+                                         .map_err(|_| {
+                                             format!("invalid parameters for curve {}", name)
+                                         }));
+        match (parts[0], &numbers[..]) {
+            ("power", &[]) => Ok(Curve::Power),
+            ("signed", &[]) => Ok(Curve::SignedPower),
+            ("log", &[]) => Ok(Curve::Log),
+            ("logistic", &[midpoint, width]) if width > 0.0 => {
+                Ok(Curve::Logistic(midpoint, width))
+            }
+            ("huber", &[delta]) if delta > 0.0 => Ok(Curve::Huber(delta)),
+            _ => {
+                Err(format!("unknown curve {}, expected power, signed, log, \
+                             logistic:MIDPOINT:WIDTH or huber:DELTA",
+                            name))
+            }
+        }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Curve::Power => write!(f, "power"),
+            Curve::SignedPower => write!(f, "signed"),
+            Curve::Log => write!(f, "log"),
+            Curve::Logistic(midpoint, width) => write!(f, "logistic:{}:{}", midpoint, width),
+            Curve::Huber(delta) => write!(f, "huber:{}", delta),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Stat {
     Mean,
     StdDev,
     Min,
     Max,
+    Median,
+    /// Linearly interpolated, between 0 and 100.
+    Percentile(f32),
+    /// Max - min.
+    Range,
+    Sum,
+    /// Number of values below the threshold.
+    CountBelow(f32),
+    /// Smooth minimum with a temperature: the minimum as the temperature
+    /// approaches 0, the mean as it grows. Unlike `Min` every value
+    /// contributes, the smaller ones the most.
+    SoftMin(f32),
+    SoftMax(f32),
+}
+
+impl Stat {
+    /// Whether the statistic is defined for the given number of values.
+    pub fn is_defined(&self, count: usize) -> bool {
+        match *self {
+            Stat::Sum | Stat::CountBelow(_) => true,
+            _ => count > 0,
+        }
+    }
+
+    /// The parameter of the statistic, with -0 normalized to 0.
+    fn key(&self) -> (u8, u32) {
+        let bits = |x: f32| unsafe { mem::transmute::<f32, u32>(x + 0.0) };
+        match *self {
+            Stat::Mean => (0, 0),
+            Stat::StdDev => (1, 0),
+            Stat::Min => (2, 0),
+            Stat::Max => (3, 0),
+            Stat::Median => (4, 0),
+            Stat::Percentile(p) => (5, bits(p)),
+            Stat::Range => (6, 0),
+            Stat::Sum => (7, 0),
+            Stat::CountBelow(threshold) => (8, bits(threshold)),
+            Stat::SoftMin(temperature) => (9, bits(temperature)),
+            Stat::SoftMax(temperature) => (10, bits(temperature)),
+        }
+    }
+}
+
+/// The parameters are never NaN, parsing rejects it.
+impl Eq for Stat {}
+
+impl Hash for Stat {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
 }
 
 pub struct StatValues {
@@ -109,28 +783,70 @@ pub struct StatValues {
     stddev: f32,
     min: f32,
     max: f32,
+    sorted: Vec<f32>,
 }
 
 impl StatValues {
     pub fn from(data: &Vec<f32>) -> StatValues {
+        let mut sorted = data.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
         StatValues {
             mean: mean(data.iter().map(|&x| x)) as f32,
             stddev: stddev(data.iter().map(|&x| x)) as f32,
             min: data.iter().fold(MAX, |min, &x| min.min(x)),
             max: data.iter().fold(MIN, |max, &x| max.max(x)),
+            sorted: sorted,
         }
     }
 
-    fn get(&self, prop: &Stat) -> f32 {
-        match prop {
+    /// `None` if the statistic is undefined for no values. The sum and count
+    /// of no values are 0 and the standard deviation of one value is 0.
+    pub fn get(&self, prop: &Stat) -> Option<f32> {
+        if !prop.is_defined(self.sorted.len()) {
+            return None;
+        }
+        Some(match prop {
             &Stat::Mean => self.mean,
             &Stat::StdDev => self.stddev,
             &Stat::Min => self.min,
             &Stat::Max => self.max,
-        }
+            &Stat::Median => percentile(&self.sorted, 50.0),
+            &Stat::Percentile(p) => percentile(&self.sorted, p),
+            &Stat::Range => self.max - self.min,
+            &Stat::Sum => self.sorted.iter().fold(0.0, |sum, &x| sum + x),
+            &Stat::CountBelow(threshold) => {
+                self.sorted.iter().take_while(|&&x| x < threshold).count() as f32
+            }
+            &Stat::SoftMin(temperature) => soft_min(&self.sorted, temperature),
+            &Stat::SoftMax(temperature) => {
+                let negated: Vec<f32> = self.sorted.iter().map(|&x| -x).collect();
+                -soft_min(&negated, temperature)
+            }
+        })
     }
 }
 
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    if sorted.is_empty() {
+        return NAN;
+    }
+    let rank = p / 100.0 * (sorted.len() - 1) as f32;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f32)
+}
+
+/// `-t ln(mean(exp(-x / t)))`, shifted by the minimum to avoid overflow.
+fn soft_min(values: &[f32], temperature: f32) -> f32 {
+    if values.is_empty() {
+        return NAN;
+    }
+    let min = values.iter().fold(MAX, |min, &x| min.min(x));
+    let mean_exp = values.iter().map(|&x| (-(x - min) / temperature).exp()).sum::<f32>() /
+                   values.len() as f32;
+    min - temperature * mean_exp.ln()
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -139,28 +855,173 @@ mod test {
     use super::TargetDirection::*;
 
 
+    const UNIT: Strength = Strength {
+        factor: 1.0,
+        exponent: 1.0,
+        curve: Curve::Power,
+    };
+
     #[test]
     fn target_calculate() {
         let mut data = FitnessData::new();
-        data.insert(Chroma,
-                    StatValues {
-                        mean: 4.0,
-                        stddev: 4.0,
-                        min: 4.0,
-                        max: 4.0,
-                    });
+        data.insert(Chroma, StatValues::from(&vec![4.0]));
         fn t(target: TargetDirection) -> Target {
             Target::new(target,
                         Mean,
                         Chroma,
                         Strength {
                             factor: 3.0,
-                            exponent: 2,
+                            exponent: 2.0,
+                            curve: Curve::Power,
                         })
         }
         assert_eq!(t(Maximize).calculate(&data), 144.0);
         assert_eq!(t(Minimize).calculate(&data), -144.0);
-        assert_eq!(t(Approximate(6.0)).calculate(&data), -36.0);
+        assert_eq!(t(Approximate(6.0, 0.0)).calculate(&data), -36.0);
+    }
+
+    #[test]
+    fn thresholds_only_penalize_violations() {
+        let mut data = FitnessData::new();
+        data.insert(Chroma, StatValues::from(&vec![4.0]));
+        fn t(target: TargetDirection) -> Target {
+            Target::new(target,
+                        Mean,
+                        Chroma,
+                        Strength {
+                            factor: 1.0,
+                            exponent: 2.0,
+                            curve: Curve::Power,
+                        })
+        }
+        assert_eq!(t(AtLeast(3.0)).calculate(&data), 0.0);
+        assert_eq!(t(AtLeast(6.0)).calculate(&data), -4.0);
+        assert_eq!(t(AtMost(1.0)).calculate(&data), -9.0);
+        assert_eq!(t(Between(1.0, 5.0)).calculate(&data), 0.0);
+        assert_eq!(t(Between(5.0, 7.0)).calculate(&data), -1.0);
+        assert_eq!(t(Approximate(6.0, 1.0)).calculate(&data), -1.0);
+        assert_eq!(t(Approximate(6.0, 1.0)).satisfied(&data), Some(false));
+        assert_eq!(t(Approximate(5.0, 1.0)).satisfied(&data), Some(true));
+        assert_eq!(t(Maximize).satisfied(&data), None);
+    }
+
+    #[test]
+    fn normalize_by_range() {
+        use colorscheme::ColorScheme;
+        use palette::Lab;
+
+        let mut descr = ColorSchemeProblemDescription::new(1, vec![]);
+        descr.set(Target::new(Maximize,
+                              Mean,
+                              Luminance,
+                              Strength {
+                                  factor: 1.0,
+                                  exponent: 1.0,
+                                  curve: Curve::Power,
+                              }));
+        let dark = ColorScheme::new(vec![Lab::new(0.2, 0.0, 0.0)]);
+        let light = ColorScheme::new(vec![Lab::new(0.7, 0.0, 0.0)]);
+        descr.normalize(&[&dark, &light]);
+        let target = &descr.fitness_targets["Mean Luminance"];
+        assert!((target.scale - 50.0).abs() < 1e-4);
+        let data = light.fitness_data(&descr);
+        assert!((target.calculate_raw(&data) - 70.0).abs() < 1e-4);
+        assert!((target.calculate(&data) - 1.4).abs() < 1e-4);
+        assert_eq!("calibration:50".parse(), Ok(Normalization::Calibration(50)));
+    }
+
+    #[test]
+    fn curves() {
+        let strength = |exponent: f32, curve: &str| {
+            Strength {
+                factor: 2.0,
+                exponent: exponent,
+                curve: curve.parse().unwrap(),
+            }
+        };
+        assert_eq!(strength(2.0, "power").calculate(-3.0), 36.0);
+        assert_eq!(strength(2.0, "signed").calculate(-3.0), -36.0);
+        assert_eq!(strength(0.5, "signed").calculate(8.0), 4.0);
+        assert_eq!(strength(1.0, "log").calculate(0.0), 0.0);
+        assert_eq!(strength(1.0, "logistic:40:5").calculate(40.0), 1.0);
+        assert_eq!(strength(1.0, "huber:4").calculate(1.0), 2.0);
+        assert_eq!(strength(1.0, "huber:4").calculate(-5.0), -32.0);
+        assert!("logistic:40".parse::<Curve>().is_err());
+    }
+
+    #[test]
+    fn order_statistics() {
+        let values = StatValues::from(&vec![4.0, 1.0, 3.0, 2.0, 10.0]);
+        assert_eq!(values.get(&Median), Some(3.0));
+        assert!((values.get(&Percentile(10.0)).unwrap() - 1.4).abs() < 1e-6);
+        assert_eq!(values.get(&Range), Some(9.0));
+        assert_eq!(values.get(&Sum), Some(20.0));
+        assert_eq!(values.get(&CountBelow(3.0)), Some(2.0));
+    }
+
+    #[test]
+    fn empty_statistics() {
+        let empty = StatValues::from(&vec![]);
+        assert_eq!(empty.get(&Min), None);
+        assert_eq!(empty.get(&StdDev), None);
+        assert_eq!(empty.get(&Sum), Some(0.0));
+        assert_eq!(empty.get(&CountBelow(3.0)), Some(0.0));
+        assert_eq!(StatValues::from(&vec![2.0]).get(&StdDev), Some(0.0));
+
+        let mut data = FitnessData::new();
+        data.insert(FreeDistance, empty);
+        let target = Target::new(AtLeast(10.0), Min, FreeDistance, UNIT);
+        assert_eq!(target.value(&data), None);
+        assert_eq!(target.calculate(&data), 0.0);
+        assert_eq!(target.satisfied(&data), None);
+        let missing = Target::new(Maximize, Mean, Chroma, UNIT);
+        assert_eq!(missing.value(&data), None);
+    }
+
+    #[test]
+    fn validate() {
+        use palette::Lab;
+
+        let mut descr = ColorSchemeProblemDescription::new(1, vec![]);
+        assert!(descr.validate().is_ok());
+        descr.set(Target::new(Maximize, Sum, FreeDistance, UNIT));
+        assert!(descr.validate().is_ok());
+        let min = Target::new(Maximize, Min, FreeDistance, UNIT);
+        assert!(descr.check_target(&min).is_err());
+        let fixed = Target::new(Maximize, Min, FixedDistance, UNIT);
+        assert!(descr.check_target(&fixed).is_err());
+        descr.fixed_colors.push(Lab::new(0.5, 0.0, 0.0));
+        assert!(descr.check_target(&fixed).is_ok());
+        descr.free_color_count = 0;
+        assert!(descr.validate().is_err());
+    }
+
+    #[test]
+    fn violation_ignores_strength() {
+        use colorscheme::ColorScheme;
+        use genetic::ProblemDescription;
+        use palette::Lab;
+
+        let mut descr = ColorSchemeProblemDescription::new(1, vec![]);
+        let mut target = Target::new(AtLeast(60.0),
+                                     Mean,
+                                     Luminance,
+                                     Strength { factor: -2.0, ..UNIT });
+        target.required = true;
+        descr.set(target);
+        let dark = ColorScheme::new(vec![Lab::new(0.5, 0.0, 0.0)]);
+        assert!((descr.constraint_violation(&dark) - 10.0).abs() < 1e-4);
+        let light = ColorScheme::new(vec![Lab::new(0.7, 0.0, 0.0)]);
+        assert_eq!(descr.constraint_violation(&light), 0.0);
+    }
+
+    #[test]
+    fn soft_min_between_min_and_mean() {
+        let values = StatValues::from(&vec![4.0, 1.0, 3.0, 2.0, 10.0]);
+        assert!((values.get(&SoftMin(0.01)).unwrap() - 1.0).abs() < 0.1);
+        assert!((values.get(&SoftMax(0.01)).unwrap() - 10.0).abs() < 0.1);
+        let soft = values.get(&SoftMin(2.0)).unwrap();
+        assert!(soft > 1.0 && soft < 4.0, "soft min {}", soft);
     }
 
 }
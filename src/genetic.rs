@@ -1,98 +1,826 @@
-use rand::{Rng, Rand};
+use rand::Rng;
+use rand::distributions::normal::StandardNormal;
 use std::cmp::Ordering;
-use rand::distributions::IndependentSample;
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
 use stats::{stddev, mean};
 
-pub trait Genotype<G:Genotype<G, P> + Clone, P: ProblemDescription<G, P>> {
-    fn rand<R: Rng>(descr: &P, rng: &mut R) -> G;
-    fn mutated<R: Rng>(&self, strength: f32, rng: &mut R) -> G;
-    fn crossover<R: Rng>(&self, other: &G, rng: &mut R) -> G;
+pub trait Genotype: Clone {
+    /// Distance in genotype space, used to measure the population's diversity.
+    fn distance(&self, other: &Self) -> f32;
+}
+
+pub trait ProblemDescription {
+    type Genotype: Genotype;
+
+    fn random_genotype<R: Rng>(&self, rng: &mut R) -> Self::Genotype;
+    fn calculate_fitness(&self, genotype: &Self::Genotype) -> f32;
+
+    /// Total violation of the hard constraints, 0 if the genotype is feasible.
+    fn constraint_violation(&self, _genotype: &Self::Genotype) -> f32 {
+        0.0
+    }
+
+    /// Fitness per priority level, most important first. Individuals are
+    /// compared level by level before their total fitness. Empty if there
+    /// is only one level.
+    fn priority_fitness(&self, _genotype: &Self::Genotype) -> Vec<f32> {
+        vec![]
+    }
+
+    /// The width of the buckets a priority level is compared in: values in
+    /// the same bucket count as ties.
+    fn priority_tolerance(&self) -> f32 {
+        0.0
+    }
+}
+
+/// Picks parents from a population sorted by descending fitness.
+pub trait Selection {
+    /// Returns the indices of `count` selected individuals.
+    fn select<G, R: Rng>(&self, individuals: &[Individual<G>], count: usize, rng: &mut R)
+                         -> Vec<usize>;
+}
+
+pub trait Crossover<G> {
+    fn crossover<R: Rng>(&self, a: &G, b: &G, rng: &mut R) -> G;
+}
+
+pub trait Mutation<G> {
+    fn mutate<R: Rng>(&self, genotype: &G, strength: f32, rng: &mut R) -> G;
+}
+
+/// A genotype together with its fitness. The fitness is only calculated once
+/// per genotype: individuals which are copied unchanged into the next
+/// generation keep it.
+#[derive(Clone, Debug)]
+pub struct Individual<G> {
+    pub genotype: G,
+    /// Own mutation strength, used by `StepSize::SelfAdaptive`.
+    pub strength: f32,
+    fitness: Option<f32>,
+    /// Constraint violation, calculated together with the fitness.
+    violation: f32,
+    priorities: Vec<f32>,
+    priority_tolerance: f32,
+    /// Fitness of the better parent if this individual is a mutant, to count
+    /// successful mutations.
+    parent_fitness: Option<f32>,
+}
+
+impl<G> Individual<G> {
+    pub fn new(genotype: G) -> Individual<G> {
+        Individual {
+            genotype: genotype,
+            strength: 1.0,
+            fitness: None,
+            violation: 0.0,
+            priorities: vec![],
+            priority_tolerance: 0.0,
+            parent_fitness: None,
+        }
+    }
+
+    /// NaN until the individual has been evaluated.
+    pub fn fitness(&self) -> f32 {
+        self.fitness.unwrap_or(::std::f32::NAN)
+    }
+
+    /// An individual whose fitness is already known, without constraint
+    /// violation or priorities. Genotypes of a problem are evaluated by
+    /// `evaluate` instead, so that `compare` sees everything.
+    pub fn evaluated(genotype: G, fitness: f32) -> Individual<G> {
+        Individual { fitness: Some(fitness), ..Individual::new(genotype) }
+    }
+
+    pub fn is_evaluated(&self) -> bool {
+        self.fitness.is_some()
+    }
+
+    pub fn violation(&self) -> f32 {
+        self.violation
+    }
+
+    pub fn is_feasible(&self) -> bool {
+        self.violation <= 0.0
+    }
+
+    pub fn priorities(&self) -> &[f32] {
+        &self.priorities
+    }
+
+    /// Deb's rules: a feasible individual beats an infeasible one, of two
+    /// infeasible ones the one with the smaller violation wins and of two
+    /// feasible ones the fitter. Fitness is compared by priority level first,
+    /// then in total. `Less` if `self` is better. NaN is worse than any
+    /// number. A level is compared in buckets of the priority tolerance
+    /// rather than by the difference, so that ties are transitive and this is
+    /// a total order as long as all individuals share the tolerance.
+    pub fn compare(&self, other: &Individual<G>) -> Ordering {
+        // a NaN violation counts as infinite
+        match compare_fitness(-self.violation, -other.violation) {
+            Ordering::Equal => {}
+            ordering => return ordering,
+        }
+        let tolerance = self.priority_tolerance.max(other.priority_tolerance);
+        let bucket = |x: f32| {
+            if tolerance > 0.0 {
+                (x / tolerance).floor()
+            } else {
+                x
+            }
+        };
+        for (&a, &b) in self.priorities.iter().zip(other.priorities.iter()) {
+            match compare_fitness(bucket(a), bucket(b)) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            }
+        }
+        compare_fitness(self.fitness(), other.fitness())
+    }
+}
+
+/// Orders fitness values from best to worst, `Less` if `a` is fitter. NaN,
+/// an undefined fitness, is worse than any number and equal to itself.
+pub fn compare_fitness(a: f32, b: f32) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => Ordering::Equal,
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        (false, false) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+    }
+}
 
-    fn get_fitness(&self) -> f32;
-    fn set_fitness(&mut self, fitness: f32);
+#[derive(Clone, Debug)]
+pub struct Generation<G> {
+    pub best: Individual<G>,
+    pub mean_fitness: f32,
+    pub sd_fitness: f32,
+    /// Fraction of the previous generation's mutants which are fitter than
+    /// their parents.
+    pub success_rate: Option<f32>,
+    /// Number of random individuals inserted because the diversity collapsed.
+    pub reseeded: usize,
+    /// All individuals evaluated in this step.
+    pub evaluated: Vec<Individual<G>>,
 }
 
-pub trait ProblemDescription<G:Genotype<G, P> + Clone, P: ProblemDescription<G, P>> {
-    fn calculate_fitness(&self, genotype: &G) -> f32;
+/// Ways to keep the population from converging to a single solution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Preservation {
+    None,
+    /// Fitness sharing: parents are selected by their fitness above the
+    /// population's worst, divided by the number of individuals within the
+    /// given genotype distance (weighted by closeness), ΔE for color schemes.
+    /// This needs all pairwise distances, so it is slow for large populations.
+    Sharing(f32),
+    /// Deterministic crowding: a child only replaces the more similar of its
+    /// parents if it is at least as fit, otherwise that parent survives.
+    Crowding,
 }
 
-pub struct Population<G: Genotype<G, P> + Clone, P: ProblemDescription<G, P>> {
-    pub genotypes: Vec<G>,
+impl Default for Preservation {
+    fn default() -> Preservation {
+        Preservation::None
+    }
+}
+
+impl FromStr for Preservation {
+    type Err = String;
+
+    /// Parses `none`, `sharing[:radius]` or `crowding`.
+    fn from_str(name: &str) -> Result<Preservation, String> {
+        let mut parts = name.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        match (kind, parts.next()) {
+            ("none", None) => Ok(Preservation::None),
+            ("sharing", None) => Ok(Preservation::Sharing(10.0)),
+            ("sharing", Some(radius)) => {
+                radius.parse()
+                      .ok()
+                      .and_then(|r: f32| if r > 0.0 { Some(r) } else { None })
+                      .map(Preservation::Sharing)
+                      .ok_or(format!("invalid radius for niching {}", name))
+            }
+            ("crowding", None) => Ok(Preservation::Crowding),
+            _ => Err(format!("unknown niching {}, expected none, sharing or crowding", name)),
+        }
+    }
+}
+
+impl fmt::Display for Preservation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Preservation::None => write!(f, "none"),
+            Preservation::Sharing(radius) => write!(f, "sharing:{}", radius),
+            Preservation::Crowding => write!(f, "crowding"),
+        }
+    }
+}
+
+/// How the mutation strength is controlled on top of the heat.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StepSize {
+    /// Mutate with the heat.
+    Fixed,
+    /// Rechenberg's 1/5th success rule: the strength is multiplied by the
+    /// factor when more than a fifth of the mutations are successful, and
+    /// divided by it when fewer are.
+    OneFifth(f32),
+    /// Every individual carries its own strength, which is inherited and
+    /// mutated log-normally with the given learning rate before it is used.
+    SelfAdaptive(f32),
+}
+
+impl Default for StepSize {
+    fn default() -> StepSize {
+        StepSize::Fixed
+    }
+}
+
+impl FromStr for StepSize {
+    type Err = String;
+
+    /// Parses `fixed`, `one-fifth[:factor]` or `self-adaptive[:tau]`.
+    fn from_str(name: &str) -> Result<StepSize, String> {
+        let mut parts = name.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let given = parts.next();
+        let parameter = |default: f32| -> Result<f32, String> {
+            match given {
+                Some(p) => {
+                    p.parse()
+                     .ok()
+                     .and_then(|x: f32| if x > 0.0 { Some(x) } else { None })
+                     .ok_or(format!("invalid parameter for step size {}", name))
+                }
+                None => Ok(default),
+            }
+        };
+        match kind {
+            "fixed" => Ok(StepSize::Fixed),
+            "one-fifth" => Ok(StepSize::OneFifth(try!(parameter(1.2)))),
+            "self-adaptive" => Ok(StepSize::SelfAdaptive(try!(parameter(0.3)))),
+            _ => {
+                Err(format!("unknown step size {}, expected fixed, one-fifth or self-adaptive",
+                            name))
+            }
+        }
+    }
+}
+
+impl fmt::Display for StepSize {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StepSize::Fixed => write!(f, "fixed"),
+            StepSize::OneFifth(factor) => write!(f, "one-fifth:{}", factor),
+            StepSize::SelfAdaptive(tau) => write!(f, "self-adaptive:{}", tau),
+        }
+    }
+}
+
+pub struct Population<P: ProblemDescription, S, C, M> {
+    pub individuals: Vec<Individual<P::Genotype>>,
+    pub selection: S,
+    pub crossover: C,
+    pub mutation: M,
+    /// Fraction of the children which are mutated.
     pub mutation_index: f32,
+    /// Probability that a child is the crossover of two parents instead of a
+    /// copy of the first one.
+    pub crossover_rate: f32,
+    /// Number of best individuals which survive unchanged.
     pub elitism: usize,
-    pub problem_description: P,
+    pub step_size: StepSize,
+    /// Strength factor adapted by `StepSize::OneFifth`.
+    pub sigma: f32,
+    pub preservation: Preservation,
+    /// When the diversity falls below the first value, this fraction of the
+    /// population is replaced by random individuals.
+    pub reseed: Option<(f32, f32)>,
+    problem_description: P,
 }
 
-impl<G: Genotype<G, P> + Clone, P: ProblemDescription<G, P>> Population<G, P> {
-    pub fn new<R: Rng>(size: usize, problem_description: P, mut rng: &mut R) -> Population<G, P> {
-        let genotypes = (0..size).map(|_| G::rand(&problem_description, &mut rng)).collect();
+impl<P, S, C, M> Population<P, S, C, M>
+    where P: ProblemDescription,
+          S: Selection,
+          C: Crossover<P::Genotype>,
+          M: Mutation<P::Genotype>
+{
+    pub fn new<R: Rng>(size: usize, problem_description: P, rng: &mut R) -> Population<P, S, C, M>
+        where S: Default,
+              C: Default,
+              M: Default
+    {
+        let individuals = (0..size)
+                              .map(|_| Individual::new(problem_description.random_genotype(rng)))
+                              .collect();
         Population {
-            genotypes: genotypes,
-            problem_description: problem_description,
+            individuals: individuals,
+            selection: S::default(),
+            crossover: C::default(),
+            mutation: M::default(),
             mutation_index: 1.0,
+            crossover_rate: 1.0,
             elitism: 1,
+            step_size: StepSize::default(),
+            sigma: 1.0,
+            preservation: Preservation::default(),
+            reseed: None,
+            problem_description: problem_description,
+        }
+    }
+
+    pub fn problem_description(&self) -> &P {
+        &self.problem_description
+    }
+
+    /// Replaces the problem, which invalidates all calculated fitness values.
+    pub fn set_problem_description(&mut self, problem_description: P) {
+        self.problem_description = problem_description;
+        for individual in self.individuals.iter_mut() {
+            individual.fitness = None;
+        }
+    }
+
+    /// Changes the problem in place, which invalidates all calculated fitness
+    /// values as well.
+    pub fn update_problem<F: FnOnce(&mut P)>(&mut self, update: F) {
+        update(&mut self.problem_description);
+        for individual in self.individuals.iter_mut() {
+            individual.fitness = None;
         }
     }
+
+    pub fn diversity(&self) -> f32 {
+        diversity(&self.individuals)
+    }
+
+    /// Replaces the given fraction of the population, but at least one
+    /// individual per genotype, by the genotypes and mutations of them.
+    pub fn seed<R: Rng>(&mut self, genotypes: &[P::Genotype], fraction: f32, rng: &mut R) {
+        if genotypes.is_empty() {
+            return;
+        }
+        let n = self.individuals.len();
+        let count = ((fraction * n as f32).ceil() as usize).max(genotypes.len()).min(n);
+        for (i, individual) in self.individuals.iter_mut().take(count).enumerate() {
+            let seed = &genotypes[i % genotypes.len()];
+            let genotype = if i < genotypes.len() {
+                seed.clone()
+            } else {
+                self.mutation.mutate(seed, 1.0, rng)
+            };
+            *individual = Individual::new(genotype);
+        }
+    }
+
+    /// Calculates the fitness of every individual which has not been evaluated yet.
+    pub fn evaluate(&mut self) {
+        for individual in self.individuals.iter_mut().filter(|i| !i.is_evaluated()) {
+            evaluate(&self.problem_description, individual);
+        }
+    }
+
+    /// Fraction of the mutants which are fitter than their parents. Each
+    /// mutant is only counted once.
+    fn take_success_rate(&mut self) -> Option<f32> {
+        let mut mutants = 0;
+        let mut successes = 0;
+        for individual in self.individuals.iter_mut() {
+            if let Some(parent_fitness) = individual.parent_fitness.take() {
+                mutants += 1;
+                if individual.fitness() > parent_fitness {
+                    successes += 1;
+                }
+            }
+        }
+        if mutants > 0 {
+            Some(successes as f32 / mutants as f32)
+        } else {
+            None
+        }
+    }
+
     pub fn next_generation<R: Rng>(&mut self,
                                    mutation_strength: f32,
                                    rng: &mut R)
-                                   -> (G, f32, f32) {
-        for genotype in self.genotypes.iter_mut() {
-            let fitness = self.problem_description.calculate_fitness(&genotype);
-            genotype.set_fitness(fitness);
+                                   -> Generation<P::Genotype> {
+        self.evaluate();
+
+        let success_rate = self.take_success_rate();
+        if let (StepSize::OneFifth(factor), Some(rate)) = (self.step_size, success_rate) {
+            if rate > 0.2 {
+                self.sigma *= factor;
+            } else if rate < 0.2 {
+                self.sigma /= factor;
+            }
+            self.sigma = self.sigma.max(1e-3).min(100.0);
         }
 
-        let mean_fitness = mean(self.genotypes.iter().map(|g| g.get_fitness())) as f32;
-        let sd_fitness = stddev(self.genotypes.iter().map(|g| g.get_fitness())) as f32;
+        let mean_fitness = mean(self.individuals.iter().map(|i| i.fitness())) as f32;
+        let sd_fitness = stddev(self.individuals.iter().map(|i| i.fitness())) as f32;
 
-        self.genotypes.sort_by(|geno_a, geno_b| {
-            if geno_a.get_fitness() > geno_b.get_fitness() {
-                Ordering::Less
-            } else if geno_a.get_fitness() < geno_b.get_fitness() {
-                Ordering::Greater
-            } else {
-                Ordering::Equal
-            }
-        });
+        sort_by_fitness(&mut self.individuals);
 
-        let old = self.genotypes.clone();
+        let old = self.individuals.clone();
         let best = old[0].clone();
 
-        let mutation_count = (self.mutation_index * self.genotypes.len() as f32).ceil() as usize;
+        let mutation_count = (self.mutation_index * self.individuals.len() as f32).ceil() as usize;
+        let child_count = self.individuals.len().saturating_sub(self.elitism);
+        let parents = match self.preservation {
+            Preservation::Sharing(radius) => {
+                let (shared, order) = shared_fitness(&old, radius);
+                self.selection
+                    .select(&shared, 2 * child_count, rng)
+                    .into_iter()
+                    .map(|i| order[i])
+                    .collect()
+            }
+            _ => self.selection.select(&old, 2 * child_count, rng),
+        };
 
-        for (i, genotype) in self.genotypes.iter_mut().skip(self.elitism).enumerate() {
-            let parent_a = tournament_selection(&old, 4, rng);
-            let parent_b = tournament_selection(&old, 4, rng);
-            let child = parent_a.crossover(&parent_b, rng);
-            // let child = parent_a.clone();
+        for (i, individual) in self.individuals.iter_mut().skip(self.elitism).enumerate() {
+            let parent_a = &old[parents[2 * i]];
+            let parent_b = &old[parents[2 * i + 1]];
+            let (child, parent_fitness) = if rng.gen::<f32>() < self.crossover_rate {
+                let mut child = Individual::new(self.crossover.crossover(&parent_a.genotype,
+                                                                         &parent_b.genotype,
+                                                                         rng));
+                child.strength = (parent_a.strength * parent_b.strength).sqrt();
+                (child, parent_a.fitness().max(parent_b.fitness()))
+            } else {
+                (parent_a.clone(), parent_a.fitness())
+            };
 
             let child = if i < mutation_count {
-                child.mutated(mutation_strength, rng)
+                let strength = match self.step_size {
+                    StepSize::Fixed => child.strength,
+                    StepSize::OneFifth(_) => self.sigma,
+                    StepSize::SelfAdaptive(tau) => {
+                        let StandardNormal(n) = rng.gen::<StandardNormal>();
+                        (child.strength * (tau as f64 * n).exp() as f32).max(1e-3).min(10.0)
+                    }
+                };
+                let effective = match self.step_size {
+                    StepSize::Fixed => mutation_strength,
+                    _ => mutation_strength * strength,
+                };
+                let mut mutant =
+                    Individual::new(self.mutation.mutate(&child.genotype, effective, rng));
+                mutant.strength = strength;
+                mutant.parent_fitness = Some(parent_fitness);
+                mutant
+            } else {
+                child
+            };
+
+            let child = if self.preservation == Preservation::Crowding {
+                let mut child = child;
+                if !child.is_evaluated() {
+                    evaluate(&self.problem_description, &mut child);
+                }
+                let closer = if child.genotype.distance(&parent_a.genotype) <=
+                                child.genotype.distance(&parent_b.genotype) {
+                    parent_a
+                } else {
+                    parent_b
+                };
+                if child.compare(closer) != Ordering::Greater {
+                    child
+                } else {
+                    closer.clone()
+                }
             } else {
                 child
             };
 
-            *genotype = child;
+            *individual = child;
         }
 
+        let mut reseeded = 0;
+        if let Some((threshold, fraction)) = self.reseed {
+            if self.diversity() < threshold {
+                reseeded = ((fraction * self.individuals.len() as f32).ceil() as usize)
+                               .min(child_count);
+                let problem_description = &self.problem_description;
+                for individual in self.individuals.iter_mut().rev().take(reseeded) {
+                    *individual = Individual::new(problem_description.random_genotype(rng));
+                }
+            }
+        }
 
-        (best, mean_fitness, sd_fitness)
+        Generation {
+            best: best,
+            mean_fitness: mean_fitness,
+            sd_fitness: sd_fitness,
+            success_rate: success_rate,
+            reseeded: reseeded,
+            evaluated: old,
+        }
     }
 }
 
-#[allow(dead_code)]
-fn tournament_selection<'a, G: Genotype<G, P> + Clone, P: ProblemDescription<G, P>, R: Rng>
-    (genotypes: &'a Vec<G>,
-     size: usize,
-     rng: &mut R)
-     -> &'a G {
-    assert!(size >= 1);
-    (0..size).fold(rng.choose(genotypes).unwrap(), |best, _| {
-        let competitor = rng.choose(genotypes).unwrap();
-        if competitor.get_fitness() > best.get_fitness() {
-            competitor
-        } else {
-            best
+/// Calculates the fitness, constraint violation and priorities of an
+/// individual, everything `Individual::compare` looks at.
+pub fn evaluate<P: ProblemDescription>(problem: &P, individual: &mut Individual<P::Genotype>) {
+    individual.fitness = Some(problem.calculate_fitness(&individual.genotype));
+    individual.violation = problem.constraint_violation(&individual.genotype);
+    individual.priorities = problem.priority_fitness(&individual.genotype);
+    individual.priority_tolerance = problem.priority_tolerance();
+}
+
+/// A new individual, evaluated by `evaluate`.
+pub fn evaluate_genotype<P: ProblemDescription>(problem: &P,
+                                                genotype: P::Genotype)
+                                                -> Individual<P::Genotype> {
+    let mut individual = Individual::new(genotype);
+    evaluate(problem, &mut individual);
+    individual
+}
+
+/// Sorts by ascending constraint violation, then by descending fitness.
+pub fn sort_by_fitness<G>(individuals: &mut [Individual<G>]) {
+    individuals.sort_by(|a, b| a.compare(b));
+}
+
+/// Mean pairwise distance between genotypes. For large populations it is
+/// estimated from 64 evenly spaced individuals.
+pub fn diversity<G: Genotype>(individuals: &[Individual<G>]) -> f32 {
+    let stride = (individuals.len() + 63) / 64;
+    let sample: Vec<&G> = individuals.iter()
+                                     .enumerate()
+                                     .filter(|&(i, _)| i % stride.max(1) == 0)
+                                     .map(|(_, individual)| &individual.genotype)
+                                     .collect();
+    if sample.len() < 2 {
+        return 0.0;
+    }
+    let mut sum = 0.0;
+    for (i, a) in sample.iter().enumerate() {
+        for b in sample[i + 1..].iter() {
+            sum += a.distance(b);
         }
-    })
+    }
+    sum / (sample.len() * (sample.len() - 1) / 2) as f32
+}
+
+/// Shared fitness of every individual as unit individuals sorted by
+/// `Individual::compare`, together with the index of the original individual
+/// at every position. The unit individuals keep the constraint violation and
+/// priorities, so tournaments still prefer feasible individuals.
+fn shared_fitness<G: Genotype>(individuals: &[Individual<G>],
+                               radius: f32)
+                               -> (Vec<Individual<()>>, Vec<usize>) {
+    let worst = individuals.iter().fold(::std::f32::MAX, |min, i| min.min(i.fitness()));
+    let mut shared: Vec<(usize, Individual<()>)> =
+        individuals.iter()
+                   .enumerate()
+                   .map(|(i, a)| {
+                       let niche_count = individuals.iter()
+                                                    .map(|b| {
+                                                        let d = a.genotype.distance(&b.genotype);
+                                                        (1.0 - d / radius).max(0.0)
+                                                    })
+                                                    .sum::<f32>();
+                       let shared = Individual {
+                           genotype: (),
+                           strength: a.strength,
+                           fitness: Some((a.fitness() - worst) / niche_count),
+                           violation: a.violation,
+                           priorities: a.priorities.clone(),
+                           priority_tolerance: a.priority_tolerance,
+                           parent_fitness: None,
+                       };
+                       (i, shared)
+                   })
+                   .collect();
+    shared.sort_by(|a, b| a.1.compare(&b.1));
+    let order = shared.iter().map(|&(i, _)| i).collect();
+    (shared.into_iter().map(|(_, individual)| individual).collect(), order)
+}
+
+/// The heat is the mutation strength, which starts at 1 whenever the problem
+/// changes and cools down to 0.01.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeatSchedule {
+    /// A heat between 0 and 1.
+    Constant(f32),
+    /// Linearly within the given number of generations.
+    Linear(usize),
+    /// Halves every given number of generations.
+    Exponential(usize),
+    /// Follows half a cosine period within the given number of generations.
+    Cosine(usize),
+    /// Like `Cosine`, but starts over after every period.
+    Restarts(usize),
+}
+
+impl Default for HeatSchedule {
+    fn default() -> HeatSchedule {
+        HeatSchedule::Linear(200)
+    }
+}
+
+impl HeatSchedule {
+    pub fn heat(&self, generations_since_change: usize) -> f32 {
+        let t = generations_since_change as f32;
+        let cosine = |period: usize, t: f32| {
+            0.5 * (1.0 + (PI * (t / period as f32).min(1.0)).cos())
+        };
+        let heat = match *self {
+            HeatSchedule::Constant(heat) => return heat,
+            HeatSchedule::Linear(duration) => 1.0 - t / duration as f32,
+            HeatSchedule::Exponential(half_life) => 0.5f32.powf(t / half_life as f32),
+            HeatSchedule::Cosine(duration) => cosine(duration, t),
+            HeatSchedule::Restarts(period) => {
+                cosine(period, (generations_since_change % period.max(1)) as f32)
+            }
+        };
+        heat.max(0.01)
+    }
+}
+
+impl FromStr for HeatSchedule {
+    type Err = String;
+
+    /// Parses `constant:HEAT` with a heat between 0 and 1, `linear:N`,
+    /// `exponential:N`, `cosine:N` or `restarts:N`.
+    fn from_str(name: &str) -> Result<HeatSchedule, String> {
+        let mut parts = name.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let parameter = parts.next().unwrap_or("");
+        let invalid = || format!("invalid parameter for heat schedule {}", name);
+        let generations = || -> Result<usize, String> {
+            match parameter.parse() {
+                Ok(n) if n > 0 => Ok(n),
+                _ => Err(invalid()),
+            }
+        };
+        match kind {
+            "constant" => {
+                match parameter.parse() {
+                    Ok(heat) if heat >= 0.0 && heat <= 1.0 => Ok(HeatSchedule::Constant(heat)),
+                    _ => Err(invalid()),
+                }
+            }
+            "linear" => generations().map(HeatSchedule::Linear),
+            "exponential" => generations().map(HeatSchedule::Exponential),
+            "cosine" => generations().map(HeatSchedule::Cosine),
+            "restarts" => generations().map(HeatSchedule::Restarts),
+            _ => {
+                Err(format!("unknown heat schedule {}, expected constant, linear, exponential, \
+                             cosine or restarts",
+                            name))
+            }
+        }
+    }
+}
+
+impl fmt::Display for HeatSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            HeatSchedule::Constant(heat) => write!(f, "constant:{}", heat),
+            HeatSchedule::Linear(n) => write!(f, "linear:{}", n),
+            HeatSchedule::Exponential(n) => write!(f, "exponential:{}", n),
+            HeatSchedule::Cosine(n) => write!(f, "cosine:{}", n),
+            HeatSchedule::Restarts(n) => write!(f, "restarts:{}", n),
+        }
+    }
+}
+
+/// The fittest of `size` randomly chosen individuals wins, compared by
+/// `Individual::compare`.
+#[derive(Clone, Copy, Debug)]
+pub struct Tournament {
+    pub size: usize,
+}
+
+impl Default for Tournament {
+    fn default() -> Tournament {
+        Tournament { size: 4 }
+    }
+}
+
+impl Selection for Tournament {
+    fn select<G, R: Rng>(&self, individuals: &[Individual<G>], count: usize, rng: &mut R)
+                         -> Vec<usize> {
+        assert!(self.size >= 1);
+        (0..count)
+            .map(|_| {
+                (1..self.size).fold(rng.gen_range(0, individuals.len()), |best, _| {
+                    let competitor = rng.gen_range(0, individuals.len());
+                    if individuals[competitor].compare(&individuals[best]) == Ordering::Less {
+                        competitor
+                    } else {
+                        best
+                    }
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cmp::Ordering;
+    use std::f32::NAN;
+    use optimizer::test::Point;
+
+    fn individual(x: f32, fitness: f32) -> Individual<Point> {
+        Individual::evaluated(Point(vec![x]), fitness)
+    }
+
+    #[test]
+    fn sharing_favors_lonely_individuals() {
+        let individuals = vec![individual(0.0, 10.0),
+                               individual(0.0, 10.0),
+                               individual(0.0, 10.0),
+                               individual(5.0, 8.0),
+                               individual(10.0, 0.0)];
+        let (shared, order) = shared_fitness(&individuals, 1.0);
+        assert_eq!(order[0], 3);
+        assert_eq!(shared[0].fitness(), 8.0);
+        assert!((shared[1].fitness() - 10.0 / 3.0).abs() < 1e-6);
+        assert_eq!(order[4], 4);
+    }
+
+    #[test]
+    fn feasible_first() {
+        let mut infeasible = individual(0.0, 10.0);
+        infeasible.violation = 2.0;
+        let mut less_infeasible = individual(0.0, 1.0);
+        less_infeasible.violation = 1.0;
+        let mut individuals = vec![infeasible, less_infeasible, individual(0.0, -5.0)];
+        sort_by_fitness(&mut individuals);
+        let order: Vec<f32> = individuals.iter().map(|i| i.fitness()).collect();
+        assert_eq!(order, vec![-5.0, 1.0, 10.0]);
+    }
+
+    #[test]
+    fn lexicographic_priorities() {
+        let prioritized = |levels: Vec<f32>, fitness: f32| {
+            let mut individual = individual(0.0, fitness);
+            individual.priorities = levels;
+            individual.priority_tolerance = 0.5;
+            individual
+        };
+        let mut individuals = vec![prioritized(vec![1.0, 0.0], 10.0),
+                                   prioritized(vec![2.0, 0.0], 0.0),
+                                   prioritized(vec![2.2, -1.0], 5.0)];
+        sort_by_fitness(&mut individuals);
+        let order: Vec<f32> = individuals.iter().map(|i| i.fitness()).collect();
+        assert_eq!(order, vec![0.0, 5.0, 10.0]);
+
+        // 0.6 and 0.4 are closer than the tolerance, but in different buckets
+        let (low, mid, high) = (prioritized(vec![0.1], 2.0),
+                                prioritized(vec![0.4], 1.0),
+                                prioritized(vec![0.6], 0.0));
+        assert_eq!(low.compare(&mid), Ordering::Less);
+        assert_eq!(high.compare(&mid), Ordering::Less);
+        assert_eq!(high.compare(&low), Ordering::Less);
+    }
+
+    #[test]
+    fn nan_ranks_last() {
+        let mut individuals = vec![individual(0.0, 1.0),
+                                   individual(0.0, NAN),
+                                   individual(0.0, -3.0),
+                                   individual(0.0, 2.0)];
+        sort_by_fitness(&mut individuals);
+        let order: Vec<f32> = individuals.iter().map(|i| i.fitness()).collect();
+        assert_eq!(&order[..3], &[2.0, 1.0, -3.0]);
+        assert!(order[3].is_nan());
+        assert_eq!(compare_fitness(NAN, NAN), Ordering::Equal);
+    }
+
+    #[test]
+    fn pairwise_diversity() {
+        assert_eq!(diversity(&[individual(1.0, 0.0), individual(1.0, 0.0)]), 0.0);
+        let spread = vec![individual(0.0, 0.0), individual(1.0, 0.0), individual(2.0, 0.0)];
+        assert!((diversity(&spread) - 4.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn heat_schedules() {
+        assert_eq!(HeatSchedule::Linear(200).heat(0), 1.0);
+        assert_eq!(HeatSchedule::Linear(200).heat(100), 0.5);
+        assert_eq!(HeatSchedule::Linear(200).heat(1000), 0.01);
+        assert_eq!(HeatSchedule::Exponential(10).heat(20), 0.25);
+        assert!((HeatSchedule::Cosine(100).heat(50) - 0.5).abs() < 1e-6);
+        assert_eq!(HeatSchedule::Restarts(100).heat(100), 1.0);
+        assert_eq!(HeatSchedule::Constant(0.3).heat(5), 0.3);
+    }
+
+    #[test]
+    fn parse_heat_schedules() {
+        assert_eq!("constant:0.3".parse::<HeatSchedule>(), Ok(HeatSchedule::Constant(0.3)));
+        assert_eq!("cosine:50".parse::<HeatSchedule>(), Ok(HeatSchedule::Cosine(50)));
+        for name in ["constant:-0.5", "constant:NaN", "constant:inf", "constant:2", "linear:0"]
+                        .iter() {
+            assert!(name.parse::<HeatSchedule>().is_err(), "{}", name);
+        }
+    }
 }
@@ -0,0 +1,110 @@
+
+use std::cmp::Ordering;
+
+use genetic::{Genotype, Individual, ProblemDescription, evaluate};
+
+/// The best genotypes seen during a run which are mutually at least
+/// `min_distance` apart, best first by `Individual::compare`. A genotype
+/// close to a member only replaces it if it is better.
+#[derive(Clone, Debug)]
+pub struct HallOfFame<G> {
+    pub capacity: usize,
+    pub min_distance: f32,
+    members: Vec<Individual<G>>,
+}
+
+impl<G: Genotype> HallOfFame<G> {
+    pub fn new(capacity: usize, min_distance: f32) -> HallOfFame<G> {
+        HallOfFame {
+            capacity: capacity,
+            min_distance: min_distance,
+            members: vec![],
+        }
+    }
+
+    pub fn members(&self) -> &[Individual<G>] {
+        &self.members
+    }
+
+    /// Returns whether the individual was admitted.
+    pub fn offer(&mut self, candidate: &Individual<G>) -> bool {
+        if self.capacity == 0 || candidate.fitness().is_nan() {
+            return false;
+        }
+        let full = self.members.len() == self.capacity;
+        if full && candidate.compare(&self.members[self.capacity - 1]) != Ordering::Less {
+            return false;
+        }
+        let close: Vec<usize> = self.members
+                                    .iter()
+                                    .enumerate()
+                                    .filter(|&(_, m)| {
+                                        m.genotype.distance(&candidate.genotype) < self.min_distance
+                                    })
+                                    .map(|(i, _)| i)
+                                    .collect();
+        if close.iter().any(|&i| candidate.compare(&self.members[i]) != Ordering::Less) {
+            return false;
+        }
+        for &i in close.iter().rev() {
+            self.members.remove(i);
+        }
+        let position = self.members
+                           .iter()
+                           .position(|m| candidate.compare(m) == Ordering::Less)
+                           .unwrap_or(self.members.len());
+        self.members.insert(position, candidate.clone());
+        self.members.truncate(self.capacity);
+        true
+    }
+
+    pub fn update(&mut self, individuals: &[Individual<G>]) {
+        for individual in individuals.iter() {
+            self.offer(individual);
+        }
+    }
+
+    /// Evaluates all members again after the problem changed.
+    pub fn reevaluate<P: ProblemDescription<Genotype = G>>(&mut self, problem: &P) {
+        for member in self.members.iter_mut() {
+            evaluate(problem, member);
+        }
+        self.members.sort_by(|a, b| a.compare(b));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use genetic::{Individual, evaluate_genotype};
+    use optimizer::test::{Point, HalfSphere};
+
+    fn individual(x: f32, fitness: f32) -> Individual<Point> {
+        Individual::evaluated(Point(vec![x]), fitness)
+    }
+
+    #[test]
+    fn keeps_distinct_best() {
+        let mut hall = HallOfFame::new(2, 1.0);
+        hall.update(&[individual(0.0, 5.0),
+                      individual(0.5, 6.0),
+                      individual(0.2, 4.0),
+                      individual(3.0, 1.0),
+                      individual(6.0, 2.0)]);
+        let members: Vec<(f32, f32)> = hall.members()
+                                           .iter()
+                                           .map(|m| (m.genotype.0[0], m.fitness()))
+                                           .collect();
+        assert_eq!(members, vec![(0.5, 6.0), (6.0, 2.0)]);
+    }
+
+    #[test]
+    fn feasible_members_win() {
+        let problem = HalfSphere(1);
+        let mut hall = HallOfFame::new(2, 1.0);
+        hall.offer(&evaluate_genotype(&problem, Point(vec![-0.1])));
+        // fitter, but infeasible
+        assert!(!hall.offer(&evaluate_genotype(&problem, Point(vec![0.3]))));
+        assert_eq!(hall.members()[0].genotype.0, vec![-0.1]);
+    }
+}
@@ -0,0 +1,173 @@
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Csv,
+    JsonLines,
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Result<LogFormat, String> {
+        match name {
+            "csv" => Ok(LogFormat::Csv),
+            "jsonl" | "json" => Ok(LogFormat::JsonLines),
+            _ => Err(format!("unknown log format {}, expected csv or jsonl", name)),
+        }
+    }
+
+    /// Guesses the format from the file extension, defaulting to csv.
+    pub fn from_path(path: &str) -> LogFormat {
+        if path.ends_with(".jsonl") || path.ends_with(".json") {
+            LogFormat::JsonLines
+        } else {
+            LogFormat::Csv
+        }
+    }
+}
+
+/// Everything recorded about one generation.
+pub struct Record {
+    /// Counts the runs of a benchmark, 0 for a single run.
+    pub run: usize,
+    pub generation: usize,
+    pub best_fitness: f32,
+    pub mean_fitness: f32,
+    pub sd_fitness: f32,
+    pub heat: f32,
+    /// Name and value of every target for the best individual.
+    pub targets: Vec<(String, f32)>,
+    pub diversity: f32,
+    /// Seconds since the start of the run.
+    pub time: f64,
+}
+
+/// Writes one record per generation, so convergence can be plotted and
+/// compared offline.
+pub struct FitnessHistory {
+    out: BufWriter<File>,
+    format: LogFormat,
+}
+
+impl FitnessHistory {
+    pub fn create<P: AsRef<Path>>(path: P, format: LogFormat) -> io::Result<FitnessHistory> {
+        let mut history = FitnessHistory {
+            out: BufWriter::new(try!(File::create(path))),
+            format: format,
+        };
+        if format == LogFormat::Csv {
+            try!(writeln!(history.out,
+                          "run,generation,best,mean,sd,heat,diversity,time,targets"));
+        }
+        Ok(history)
+    }
+
+    pub fn record(&mut self, record: &Record) -> io::Result<()> {
+        try!(writeln!(self.out, "{}", format_record(record, self.format)));
+        self.out.flush()
+    }
+}
+
+/// One line of the log, without the line break.
+fn format_record(record: &Record, format: LogFormat) -> String {
+    match format {
+        LogFormat::Csv => {
+            // targets change while running, so they share one column
+            let targets: Vec<String> = record.targets
+                                             .iter()
+                                             .map(|&(ref name, value)| {
+                                                 format!("{}={}", name, value)
+                                             })
+                                             .collect();
+            format!("{},{},{},{},{},{},{},{:.3},{}",
+                    record.run,
+                    record.generation,
+                    record.best_fitness,
+                    record.mean_fitness,
+                    record.sd_fitness,
+                    record.heat,
+                    record.diversity,
+                    record.time,
+                    csv_quote(&targets.join(";")))
+        }
+        LogFormat::JsonLines => {
+            let targets: Vec<String> = record.targets
+                                             .iter()
+                                             .map(|&(ref name, value)| {
+                                                 format!("\"{}\":{}",
+                                                         json_escape(name),
+                                                         json_number(value))
+                                             })
+                                             .collect();
+            format!("{{\"run\":{},\"generation\":{},\"best\":{},\"mean\":{},\"sd\":{},\
+                     \"heat\":{},\"diversity\":{},\"time\":{:.3},\"targets\":{{{}}}}}",
+                    record.run,
+                    record.generation,
+                    json_number(record.best_fitness),
+                    json_number(record.mean_fitness),
+                    json_number(record.sd_fitness),
+                    json_number(record.heat),
+                    json_number(record.diversity),
+                    record.time,
+                    targets.join(","))
+        }
+    }
+}
+
+/// Target names may contain commas (`max(1, 2)`), so the field is always
+/// quoted, with quotes doubled as in RFC 4180.
+fn csv_quote(field: &str) -> String {
+    format!("\"{}\"", field.replace('"', "\"\""))
+}
+
+fn json_escape(string: &str) -> String {
+    string.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// JSON has no representation for NaN and infinities.
+fn json_number(value: f32) -> String {
+    if value.is_finite() {
+        format!("{}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::f32::{INFINITY, NAN};
+
+    fn record(targets: Vec<(String, f32)>) -> Record {
+        Record {
+            run: 1,
+            generation: 12,
+            best_fitness: 2.5,
+            mean_fitness: NAN,
+            sd_fitness: 0.5,
+            heat: 0.25,
+            targets: targets,
+            diversity: INFINITY,
+            time: 1.0,
+        }
+    }
+
+    #[test]
+    fn csv_quotes_targets() {
+        let record = record(vec![("max(1, 2)".to_string(), 2.0),
+                                 ("say \"hi\"".to_string(), 1.5)]);
+        assert_eq!(format_record(&record, LogFormat::Csv),
+                   "1,12,2.5,NaN,0.5,0.25,inf,1.000,\"max(1, 2)=2;say \"\"hi\"\"=1.5\"");
+    }
+
+    #[test]
+    fn json_has_no_nan() {
+        let record = record(vec![("say \"hi\"".to_string(), NAN), ("min".to_string(), 3.0)]);
+        assert_eq!(format_record(&record, LogFormat::JsonLines),
+                   "{\"run\":1,\"generation\":12,\"best\":2.5,\"mean\":null,\"sd\":0.5,\
+                    \"heat\":0.25,\"diversity\":null,\"time\":1.000,\
+                    \"targets\":{\"say \\\"hi\\\"\":null,\"min\":3}}");
+    }
+}
@@ -0,0 +1,1213 @@
+//! A small interpreter for fitness scripts. The language is a subset of
+//! Rhai:
+//!
+//! ```text
+//! // comments, like /* this */
+//! let x = 1.5;                 // int, float, bool, "string" and [arrays]
+//! x = x * 2.0 + free_count;    // ints mix with floats, also += -= *= /=
+//! if x > 3.0 && !false { x = 3.0 } else if x < 0.0 { x = 0.0 } else { }
+//! while x > 1.0 { x -= 1.0; }
+//! loop { break; }
+//! for color in free { x += color.l; }
+//! for i in range(0, 10) { if i % 2 == 0 { continue; } }
+//! x.max(2.0) + abs(-1)         // f(a, b) can be written as a.f(b)
+//! ```
+//!
+//! The value of a script, a block and an `if` is the value of its last
+//! statement, unless that ends with a semicolon. `return` ends the script
+//! with a value.
+//!
+//! Besides `len` and `range`, the functions are the numeric `abs`, `sqrt`,
+//! `ln`, `exp`, `pow`, `floor`, `ceil`, `round`, `sin`, `cos`, `to_radians`,
+//! `min`, `max`, `to_int` and `to_float`.
+//!
+//! Scripts cannot print, read files or call anything outside this module.
+//! Besides the operations, the size of strings and arrays and the number of
+//! named scores are limited, so a script can neither run nor allocate without
+//! bound.
+
+use std::fmt;
+use std::rc::Rc;
+
+/// Operations a script may run for a single scheme.
+pub const MAX_OPERATIONS: u64 = 1000000;
+/// Nesting depth of blocks and expressions, which bounds the recursion of
+/// the parser and the interpreter.
+const MAX_DEPTH: usize = 64;
+/// Length of a string in bytes.
+pub const MAX_STRING_SIZE: usize = 10000;
+/// Number of items in an array, including those made by `range`.
+pub const MAX_ARRAY_SIZE: usize = 10000;
+/// Calls of `scores.add` for a single scheme.
+pub const MAX_SCORES: usize = 1000;
+
+const KEYWORDS: &'static [&'static str] = &["let", "if", "else", "while", "loop", "for", "in",
+                                            "break", "continue", "return", "true", "false"];
+/// Longest first, so that `<=` is not read as `<` and `=`.
+const SYMBOLS: &'static [&'static str] = &["==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=",
+                                           "/=", "(", ")", "{", "}", "[", "]", ",", ";", ".",
+                                           "=", "<", ">", "+", "-", "*", "/", "%", "!"];
+const ASSIGNMENTS: &'static [&'static str] = &["=", "+=", "-=", "*=", "/="];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(Rc<String>),
+    Array(Rc<Vec<Value>>),
+    /// Named read-only properties, like the channels of a color.
+    Object(Rc<Vec<(String, Value)>>),
+    /// Collects named scores with `scores.add(name, value)`.
+    Scores,
+}
+
+impl Value {
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            Value::Int(n) => Some(n as f64),
+            Value::Float(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Unit => "nothing",
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Str(_) => "string",
+            Value::Array(_) => "array",
+            Value::Object(_) => "object",
+            Value::Scores => "scores",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// A name or a keyword.
+    Ident(String),
+    Symbol(&'static str),
+    End,
+}
+
+fn describe(token: &Token) -> String {
+    match *token {
+        Token::Int(n) => n.to_string(),
+        Token::Float(x) => x.to_string(),
+        Token::Str(ref string) => format!("\"{}\"", string),
+        Token::Ident(ref name) => name.clone(),
+        Token::Symbol(symbol) => format!("'{}'", symbol),
+        Token::End => "the end".to_string(),
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, Position)>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    let mut line = 1;
+    let mut line_start = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let position = Position {
+            line: line,
+            column: i - line_start + 1,
+        };
+        if c == '\n' {
+            line += 1;
+            line_start = i + 1;
+            i += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            loop {
+                if i + 1 >= chars.len() {
+                    return Err(format!("{}: unterminated comment", position));
+                }
+                if chars[i] == '*' && chars[i + 1] == '/' {
+                    i += 2;
+                    break;
+                }
+                if chars[i] == '\n' {
+                    line += 1;
+                    line_start = i + 1;
+                }
+                i += 1;
+            }
+            continue;
+        }
+
+        let start = i;
+        let is_digit = |i: usize| chars.get(i).map_or(false, |c| c.is_digit(10));
+        let token = if c.is_digit(10) {
+            while is_digit(i) || chars.get(i) == Some(&'_') {
+                i += 1;
+            }
+            let mut float = false;
+            if chars.get(i) == Some(&'.') && is_digit(i + 1) {
+                float = true;
+                i += 1;
+                while is_digit(i) {
+                    i += 1;
+                }
+            }
+            if chars.get(i) == Some(&'e') || chars.get(i) == Some(&'E') {
+                let sign = chars.get(i + 1) == Some(&'-') || chars.get(i + 1) == Some(&'+');
+                let digits = if sign { i + 2 } else { i + 1 };
+                if is_digit(digits) {
+                    float = true;
+                    i = digits;
+                    while is_digit(i) {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().filter(|&&c| c != '_').cloned().collect();
+            if float {
+                Token::Float(text.parse().unwrap())
+            } else {
+                match text.parse() {
+                    Ok(n) => Token::Int(n),
+                    Err(_) => return Err(format!("{}: {} is too large for an int", position, text)),
+                }
+            }
+        } else if c.is_alphabetic() || c == '_' {
+            while chars.get(i).map_or(false, |&c| c.is_alphanumeric() || c == '_') {
+                i += 1;
+            }
+            Token::Ident(chars[start..i].iter().cloned().collect())
+        } else if c == '"' {
+            i += 1;
+            let mut string = String::new();
+            loop {
+                match chars.get(i).cloned() {
+                    None | Some('\n') => {
+                        return Err(format!("{}: unterminated string", position));
+                    }
+                    Some('"') => {
+                        i += 1;
+                        break;
+                    }
+                    Some('\\') => {
+                        string.push(match chars.get(i + 1).cloned() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('"') => '"',
+                            Some('\\') => '\\',
+                            _ => return Err(format!("{}: unknown escape in string", position)),
+                        });
+                        i += 2;
+                    }
+                    Some(c) => {
+                        string.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            Token::Str(string)
+        } else {
+            let symbol = SYMBOLS.iter().cloned().find(|symbol| {
+                symbol.chars().enumerate().all(|(k, s)| chars.get(i + k) == Some(&s))
+            });
+            match symbol {
+                Some(symbol) => {
+                    i += symbol.len();
+                    Token::Symbol(symbol)
+                }
+                None => return Err(format!("{}: unexpected character {}", position, c)),
+            }
+        };
+        tokens.push((token, position));
+    }
+    tokens.push((Token::End,
+                 Position {
+                     line: line,
+                     column: i - line_start + 1,
+                 }));
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Variable(String),
+    Array(Vec<Node>),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+    Index(Box<Node>, Box<Node>),
+    Property(Box<Node>, String),
+    /// A method call `a.f(b)` is the call `f(a, b)`.
+    Call(String, Vec<Node>),
+}
+
+#[derive(Debug)]
+struct Node {
+    expr: Expr,
+    position: Position,
+    /// Of the expression tree, to bound the recursion.
+    height: usize,
+}
+
+#[derive(Debug)]
+enum Statement {
+    Let(String, Node),
+    /// The variable, where it is, the operator of a compound assignment like
+    /// `+=` and the value.
+    Assign(String, Position, Option<&'static str>, Node),
+    /// `else if` is an `If` alone in the else block.
+    If(Node, Vec<Statement>, Vec<Statement>),
+    While(Node, Vec<Statement>),
+    Loop(Vec<Statement>),
+    For(String, Node, Vec<Statement>),
+    Break,
+    Continue,
+    Return(Option<Node>),
+    /// Whether it ends with a semicolon, which discards the value.
+    Expression(Node, bool),
+}
+
+struct Parser {
+    tokens: Vec<(Token, Position)>,
+    next: usize,
+    depth: usize,
+    /// Number of loops around the current statement.
+    loops: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    fn position(&self) -> Position {
+        self.tokens[self.next].1
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].0.clone();
+        if self.next + 1 < self.tokens.len() {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn at_end(&self) -> bool {
+        *self.peek() == Token::End
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        match *self.peek() {
+            Token::Symbol(s) => s == symbol,
+            _ => false,
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        match *self.peek() {
+            Token::Ident(ref name) => name == keyword,
+            _ => false,
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> String {
+        format!("{}: expected {}, found {}", self.position(), expected, describe(self.peek()))
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), String> {
+        if self.is_symbol(symbol) {
+            self.advance();
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", symbol)))
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            Err(format!("{}: nested too deeply", self.position()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let position = self.position();
+        match self.advance() {
+            Token::Ident(ref name) if !KEYWORDS.contains(&&name[..]) => Ok(name.clone()),
+            token => Err(format!("{}: expected a name, found {}", position, describe(&token))),
+        }
+    }
+
+    /// Statements up to the closing brace if there is one, else up to the
+    /// end of the script.
+    fn statements(&mut self, braced: bool) -> Result<Vec<Statement>, String> {
+        let mut statements = vec![];
+        loop {
+            if braced && self.is_symbol("}") {
+                self.advance();
+                return Ok(statements);
+            }
+            if self.at_end() {
+                if braced {
+                    return Err(self.unexpected("'}'"));
+                }
+                return Ok(statements);
+            }
+            if self.is_symbol(";") {
+                self.advance();
+                continue;
+            }
+            statements.push(try!(self.statement()));
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Statement>, String> {
+        try!(self.expect("{"));
+        try!(self.enter());
+        let statements = try!(self.statements(true));
+        self.depth -= 1;
+        Ok(statements)
+    }
+
+    fn loop_body(&mut self) -> Result<Vec<Statement>, String> {
+        self.loops += 1;
+        let body = try!(self.block());
+        self.loops -= 1;
+        Ok(body)
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, String> {
+        self.advance();
+        let condition = try!(self.expression());
+        let then = try!(self.block());
+        let otherwise = if self.is_keyword("else") {
+            self.advance();
+            if self.is_keyword("if") {
+                vec![try!(self.if_statement())]
+            } else {
+                try!(self.block())
+            }
+        } else {
+            vec![]
+        };
+        Ok(Statement::If(condition, then, otherwise))
+    }
+
+    fn statement(&mut self) -> Result<Statement, String> {
+        // statements ending with a block need no semicolon
+        if self.is_keyword("if") {
+            return self.if_statement();
+        }
+        if self.is_keyword("while") {
+            self.advance();
+            let condition = try!(self.expression());
+            return Ok(Statement::While(condition, try!(self.loop_body())));
+        }
+        if self.is_keyword("loop") {
+            self.advance();
+            return Ok(Statement::Loop(try!(self.loop_body())));
+        }
+        if self.is_keyword("for") {
+            self.advance();
+            let name = try!(self.name());
+            if !self.is_keyword("in") {
+                return Err(self.unexpected("in"));
+            }
+            self.advance();
+            let array = try!(self.expression());
+            return Ok(Statement::For(name, array, try!(self.loop_body())));
+        }
+
+        let position = self.position();
+        let statement = if self.is_keyword("let") {
+            self.advance();
+            let name = try!(self.name());
+            try!(self.expect("="));
+            Statement::Let(name, try!(self.expression()))
+        } else if self.is_keyword("break") || self.is_keyword("continue") {
+            let is_break = self.is_keyword("break");
+            self.advance();
+            if self.loops == 0 {
+                return Err(format!("{}: {} outside of a loop",
+                                   position,
+                                   if is_break { "break" } else { "continue" }));
+            }
+            if is_break {
+                Statement::Break
+            } else {
+                Statement::Continue
+            }
+        } else if self.is_keyword("return") {
+            self.advance();
+            if self.is_symbol(";") || self.is_symbol("}") || self.at_end() {
+                Statement::Return(None)
+            } else {
+                Statement::Return(Some(try!(self.expression())))
+            }
+        } else {
+            let expression = try!(self.expression());
+            let assignment = ASSIGNMENTS.iter()
+                                        .cloned()
+                                        .find(|assignment| self.is_symbol(assignment));
+            match assignment {
+                Some(assignment) => {
+                    let name = match expression.expr {
+                        Expr::Variable(ref name) => name.clone(),
+                        _ => return Err(format!("{}: only variables can be assigned to", position)),
+                    };
+                    self.advance();
+                    let operator = if assignment == "=" {
+                        None
+                    } else {
+                        Some(&assignment[..1])
+                    };
+                    Statement::Assign(name, position, operator, try!(self.expression()))
+                }
+                None => Statement::Expression(expression, self.is_symbol(";")),
+            }
+        };
+        if self.is_symbol(";") {
+            self.advance();
+        } else if !self.is_symbol("}") && !self.at_end() {
+            return Err(self.unexpected("';'"));
+        }
+        Ok(statement)
+    }
+
+    fn node(&self, expr: Expr, position: Position) -> Result<Node, String> {
+        let height = 1 +
+                     match expr {
+            Expr::Literal(_) | Expr::Variable(_) => 0,
+            Expr::Array(ref items) | Expr::Call(_, ref items) => {
+                items.iter().map(|item| item.height).max().unwrap_or(0)
+            }
+            Expr::Unary(_, ref operand) | Expr::Property(ref operand, _) => operand.height,
+            Expr::Binary(_, ref a, ref b) | Expr::Index(ref a, ref b) => a.height.max(b.height),
+        };
+        if height > MAX_DEPTH {
+            return Err(format!("{}: nested too deeply", position));
+        }
+        Ok(Node {
+            expr: expr,
+            position: position,
+            height: height,
+        })
+    }
+
+    fn expression(&mut self) -> Result<Node, String> {
+        try!(self.enter());
+        let node = try!(self.binary(&["||"], Parser::and));
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    /// Left-associative operators of the same precedence.
+    fn binary<F>(&mut self, operators: &[&'static str], operand: F) -> Result<Node, String>
+        where F: Fn(&mut Parser) -> Result<Node, String>
+    {
+        let mut left = try!(operand(self));
+        loop {
+            let operator = operators.iter().cloned().find(|operator| self.is_symbol(operator));
+            let operator = match operator {
+                Some(operator) => operator,
+                None => return Ok(left),
+            };
+            let position = self.position();
+            self.advance();
+            let right = try!(operand(self));
+            left = try!(self.node(Expr::Binary(operator, Box::new(left), Box::new(right)),
+                                  position));
+        }
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        self.binary(&["&&"], Parser::equality)
+    }
+
+    fn equality(&mut self) -> Result<Node, String> {
+        self.binary(&["==", "!="], Parser::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        self.binary(&["<", "<=", ">", ">="], Parser::sum)
+    }
+
+    fn sum(&mut self) -> Result<Node, String> {
+        self.binary(&["+", "-"], Parser::product)
+    }
+
+    fn product(&mut self) -> Result<Node, String> {
+        self.binary(&["*", "/", "%"], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        let position = self.position();
+        let operator = if self.is_symbol("-") {
+            "-"
+        } else if self.is_symbol("!") {
+            "!"
+        } else {
+            return self.postfix();
+        };
+        self.advance();
+        try!(self.enter());
+        let operand = try!(self.unary());
+        self.depth -= 1;
+        self.node(Expr::Unary(operator, Box::new(operand)), position)
+    }
+
+    fn postfix(&mut self) -> Result<Node, String> {
+        let mut node = try!(self.primary());
+        loop {
+            let position = self.position();
+            if self.is_symbol("[") {
+                self.advance();
+                let index = try!(self.expression());
+                try!(self.expect("]"));
+                node = try!(self.node(Expr::Index(Box::new(node), Box::new(index)), position));
+            } else if self.is_symbol(".") {
+                self.advance();
+                let name = try!(self.name());
+                let expr = if self.is_symbol("(") {
+                    self.advance();
+                    let mut arguments = vec![node];
+                    arguments.extend(try!(self.list(")")));
+                    Expr::Call(name, arguments)
+                } else {
+                    Expr::Property(Box::new(node), name)
+                };
+                node = try!(self.node(expr, position));
+            } else {
+                return Ok(node);
+            }
+        }
+    }
+
+    /// Comma separated expressions up to the closing symbol. A trailing
+    /// comma is allowed.
+    fn list(&mut self, closing: &str) -> Result<Vec<Node>, String> {
+        let mut items = vec![];
+        while !self.is_symbol(closing) {
+            items.push(try!(self.expression()));
+            if !self.is_symbol(closing) {
+                try!(self.expect(","));
+            }
+        }
+        self.advance();
+        Ok(items)
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        let position = self.position();
+        match self.advance() {
+            Token::Int(n) => self.node(Expr::Literal(Value::Int(n)), position),
+            Token::Float(x) => self.node(Expr::Literal(Value::Float(x)), position),
+            Token::Str(string) => self.node(Expr::Literal(Value::Str(Rc::new(string))), position),
+            Token::Symbol("(") => {
+                let node = try!(self.expression());
+                try!(self.expect(")"));
+                Ok(node)
+            }
+            Token::Symbol("[") => {
+                let items = try!(self.list("]"));
+                self.node(Expr::Array(items), position)
+            }
+            Token::Ident(name) => {
+                if name == "true" || name == "false" {
+                    return self.node(Expr::Literal(Value::Bool(name == "true")), position);
+                }
+                if KEYWORDS.contains(&&name[..]) {
+                    return Err(format!("{}: unexpected {}", position, name));
+                }
+                let expr = if self.is_symbol("(") {
+                    self.advance();
+                    Expr::Call(name, try!(self.list(")")))
+                } else {
+                    Expr::Variable(name)
+                };
+                self.node(expr, position)
+            }
+            token => Err(format!("{}: expected a value, found {}", position, describe(&token))),
+        }
+    }
+}
+
+/// A parsed script.
+#[derive(Debug)]
+pub struct Program {
+    statements: Vec<Statement>,
+}
+
+impl Program {
+    pub fn parse(source: &str) -> Result<Program, String> {
+        let mut parser = Parser {
+            tokens: try!(tokenize(source)),
+            next: 0,
+            depth: 0,
+            loops: 0,
+        };
+        Ok(Program { statements: try!(parser.statements(false)) })
+    }
+
+    /// Runs the script with the given variables and returns its value and
+    /// the scores it added.
+    pub fn run(&self,
+               variables: Vec<(String, Value)>)
+               -> Result<(Value, Vec<(String, f64)>), String> {
+        let mut machine = Machine {
+            variables: variables,
+            operations: 0,
+            scores: vec![],
+        };
+        let value = match try!(machine.block(&self.statements)) {
+            Flow::Normal(value) | Flow::Return(value) => value,
+            Flow::Break | Flow::Continue => Value::Unit,
+        };
+        Ok((value, machine.scores))
+    }
+}
+
+enum Flow {
+    Normal(Value),
+    Break,
+    Continue,
+    Return(Value),
+}
+
+struct Machine {
+    /// Innermost last, so that later variables shadow earlier ones.
+    variables: Vec<(String, Value)>,
+    operations: u64,
+    scores: Vec<(String, f64)>,
+}
+
+impl Machine {
+    fn count(&mut self) -> Result<(), String> {
+        self.operations += 1;
+        if self.operations > MAX_OPERATIONS {
+            Err(format!("more than {} operations", MAX_OPERATIONS))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn block(&mut self, statements: &[Statement]) -> Result<Flow, String> {
+        try!(self.count());
+        let scope = self.variables.len();
+        let mut value = Value::Unit;
+        for statement in statements.iter() {
+            match try!(self.statement(statement)) {
+                Flow::Normal(v) => value = v,
+                flow => {
+                    self.variables.truncate(scope);
+                    return Ok(flow);
+                }
+            }
+        }
+        self.variables.truncate(scope);
+        Ok(Flow::Normal(value))
+    }
+
+    fn condition(&mut self, node: &Node) -> Result<bool, String> {
+        match try!(self.eval(node)) {
+            Value::Bool(b) => Ok(b),
+            value => {
+                Err(format!("{}: expected a bool, found {}", node.position, value.type_name()))
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<Flow, String> {
+        match *statement {
+            Statement::Let(ref name, ref node) => {
+                let value = try!(self.eval(node));
+                self.variables.push((name.clone(), value));
+            }
+            Statement::Assign(ref name, position, operator, ref node) => {
+                let value = try!(self.eval(node));
+                let slot = match self.variables.iter().rposition(|&(ref n, _)| n == name) {
+                    Some(slot) => slot,
+                    None => return Err(format!("{}: unknown variable {}", position, name)),
+                };
+                let value = match operator {
+                    Some(operator) => {
+                        try!(binary(operator, &self.variables[slot].1, &value, position))
+                    }
+                    None => value,
+                };
+                self.variables[slot].1 = value;
+            }
+            Statement::If(ref condition, ref then, ref otherwise) => {
+                let branch = if try!(self.condition(condition)) {
+                    then
+                } else {
+                    otherwise
+                };
+                return self.block(branch);
+            }
+            Statement::While(ref condition, ref body) => {
+                while try!(self.condition(condition)) {
+                    match try!(self.block(body)) {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal(_) | Flow::Continue => {}
+                    }
+                }
+            }
+            Statement::Loop(ref body) => {
+                loop {
+                    match try!(self.block(body)) {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal(_) | Flow::Continue => {}
+                    }
+                }
+            }
+            Statement::For(ref name, ref node, ref body) => {
+                let items = match try!(self.eval(node)) {
+                    Value::Array(items) => items,
+                    value => {
+                        return Err(format!("{}: for expects an array, found {}",
+                                           node.position,
+                                           value.type_name()))
+                    }
+                };
+                for item in items.iter() {
+                    self.variables.push((name.clone(), item.clone()));
+                    let flow = try!(self.block(body));
+                    self.variables.pop();
+                    match flow {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal(_) | Flow::Continue => {}
+                    }
+                }
+            }
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Continue => return Ok(Flow::Continue),
+            Statement::Return(ref node) => {
+                let value = match *node {
+                    Some(ref node) => try!(self.eval(node)),
+                    None => Value::Unit,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Statement::Expression(ref node, discarded) => {
+                let value = try!(self.eval(node));
+                return Ok(Flow::Normal(if discarded { Value::Unit } else { value }));
+            }
+        }
+        Ok(Flow::Normal(Value::Unit))
+    }
+
+    fn eval(&mut self, node: &Node) -> Result<Value, String> {
+        try!(self.count());
+        let position = node.position;
+        match node.expr {
+            Expr::Literal(ref value) => Ok(value.clone()),
+            Expr::Variable(ref name) => {
+                let value = self.variables
+                                .iter()
+                                .rev()
+                                .find(|&&(ref n, _)| n == name)
+                                .map(|&(_, ref value)| value.clone());
+                value.ok_or(format!("{}: unknown variable {}", position, name))
+            }
+            Expr::Array(ref items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items.iter() {
+                    values.push(try!(self.eval(item)));
+                }
+                sized_array(values, position)
+            }
+            Expr::Unary(operator, ref operand) => {
+                match (operator, try!(self.eval(operand))) {
+                    ("-", Value::Int(n)) => {
+                        n.checked_neg()
+                         .map(Value::Int)
+                         .ok_or(format!("{}: int overflow", position))
+                    }
+                    ("-", Value::Float(x)) => Ok(Value::Float(-x)),
+                    ("!", Value::Bool(b)) => Ok(Value::Bool(!b)),
+                    (_, value) => {
+                        Err(format!("{}: cannot apply {} to {}",
+                                    position,
+                                    operator,
+                                    value.type_name()))
+                    }
+                }
+            }
+            Expr::Binary(operator, ref left, ref right) => {
+                if operator == "&&" || operator == "||" {
+                    // only evaluates the right side if it matters
+                    let left = try!(self.condition(left));
+                    if left == (operator == "||") {
+                        return Ok(Value::Bool(left));
+                    }
+                    return self.condition(right).map(Value::Bool);
+                }
+                let left = try!(self.eval(left));
+                let right = try!(self.eval(right));
+                binary(operator, &left, &right, position)
+            }
+            Expr::Index(ref array, ref index) => {
+                match (try!(self.eval(array)), try!(self.eval(index))) {
+                    (Value::Array(items), Value::Int(i)) => {
+                        if i >= 0 && (i as u64) < items.len() as u64 {
+                            let item = items[i as usize].clone();
+                            Ok(item)
+                        } else {
+                            Err(format!("{}: index {} is out of bounds for an array of {}",
+                                        position,
+                                        i,
+                                        items.len()))
+                        }
+                    }
+                    (Value::Array(_), index) => {
+                        Err(format!("{}: expected an int index, found {}",
+                                    position,
+                                    index.type_name()))
+                    }
+                    (value, _) => Err(format!("{}: cannot index {}", position, value.type_name())),
+                }
+            }
+            Expr::Property(ref object, ref name) => {
+                match try!(self.eval(object)) {
+                    Value::Object(properties) => {
+                        let value = properties.iter()
+                                              .find(|&&(ref n, _)| n == name)
+                                              .map(|&(_, ref value)| value.clone());
+                        value.ok_or(format!("{}: unknown property {}", position, name))
+                    }
+                    value => {
+                        Err(format!("{}: {} has no property {}",
+                                    position,
+                                    value.type_name(),
+                                    name))
+                    }
+                }
+            }
+            Expr::Call(ref name, ref arguments) => {
+                let mut values = Vec::with_capacity(arguments.len());
+                for argument in arguments.iter() {
+                    values.push(try!(self.eval(argument)));
+                }
+                self.call(name, values, position)
+            }
+        }
+    }
+
+    fn call(&mut self,
+            name: &str,
+            arguments: Vec<Value>,
+            position: Position)
+            -> Result<Value, String> {
+        let ints: Option<Vec<i64>> = arguments.iter()
+                                              .map(|argument| {
+                                                  match *argument {
+                                                      Value::Int(n) => Some(n),
+                                                      _ => None,
+                                                  }
+                                              })
+                                              .collect();
+        let floats: Option<Vec<f64>> = arguments.iter().map(Value::as_float).collect();
+        let invalid = || {
+            let types: Vec<&str> = arguments.iter().map(Value::type_name).collect();
+            format!("{}: {} does not take ({})", position, name, types.join(", "))
+        };
+        if name == "add" && arguments.len() == 3 && arguments[0] == Value::Scores {
+            return match (&arguments[1], arguments[2].as_float()) {
+                (&Value::Str(ref score), Some(value)) => {
+                    if self.scores.len() >= MAX_SCORES {
+                        return Err(format!("{}: more than {} scores", position, MAX_SCORES));
+                    }
+                    self.scores.push(((**score).clone(), value));
+                    Ok(Value::Unit)
+                }
+                _ => Err(invalid()),
+            };
+        }
+        if name == "len" && arguments.len() == 1 {
+            return match arguments[0] {
+                Value::Array(ref items) => Ok(Value::Int(items.len() as i64)),
+                Value::Str(ref string) => Ok(Value::Int(string.chars().count() as i64)),
+                _ => Err(invalid()),
+            };
+        }
+        if let Some(ref ints) = ints {
+            match (name, &ints[..]) {
+                ("abs", &[n]) if n != ::std::i64::MIN => return Ok(Value::Int(n.abs())),
+                ("min", &[a, b]) => return Ok(Value::Int(a.min(b))),
+                ("max", &[a, b]) => return Ok(Value::Int(a.max(b))),
+                ("to_int", &[n]) => return Ok(Value::Int(n)),
+                ("range", &[start, end]) => {
+                    if end > start &&
+                       end.checked_sub(start).map_or(true, |n| n > MAX_ARRAY_SIZE as i64) {
+                        return Err(format!("{}: more than {} array items",
+                                           position,
+                                           MAX_ARRAY_SIZE));
+                    }
+                    let items = (start..end.max(start)).map(Value::Int).collect();
+                    return Ok(Value::Array(Rc::new(items)));
+                }
+                _ => {}
+            }
+        }
+        let floats = match floats {
+            Some(floats) => floats,
+            None => return Err(invalid()),
+        };
+        let value = match (name, &floats[..]) {
+            ("abs", &[x]) => x.abs(),
+            ("sqrt", &[x]) => x.sqrt(),
+            ("ln", &[x]) => x.ln(),
+            ("exp", &[x]) => x.exp(),
+            ("pow", &[x, y]) => x.powf(y),
+            ("floor", &[x]) => x.floor(),
+            ("ceil", &[x]) => x.ceil(),
+            ("round", &[x]) => x.round(),
+            ("sin", &[x]) => x.sin(),
+            ("cos", &[x]) => x.cos(),
+            ("to_radians", &[x]) => x.to_radians(),
+            ("min", &[x, y]) => x.min(y),
+            ("max", &[x, y]) => x.max(y),
+            ("to_float", &[x]) => x,
+            ("to_int", &[x]) => {
+                // the range of i64, as far as a float can tell
+                if x.is_finite() && x.abs() < 9.2e18 {
+                    return Ok(Value::Int(x as i64));
+                }
+                return Err(format!("{}: {} does not fit into an int", position, x));
+            }
+            _ => {
+                let known = ["add", "len", "range", "abs", "sqrt", "ln", "exp", "pow", "floor",
+                             "ceil", "round", "sin", "cos", "to_radians", "min", "max",
+                             "to_int", "to_float"];
+                if known.contains(&name) {
+                    return Err(invalid());
+                }
+                return Err(format!("{}: unknown function {}", position, name));
+            }
+        };
+        Ok(Value::Float(value))
+    }
+}
+
+fn compare<T: PartialOrd>(operator: &str, a: T, b: T) -> Option<bool> {
+    match operator {
+        "==" => Some(a == b),
+        "!=" => Some(a != b),
+        "<" => Some(a < b),
+        "<=" => Some(a <= b),
+        ">" => Some(a > b),
+        ">=" => Some(a >= b),
+        _ => None,
+    }
+}
+
+fn sized_array(items: Vec<Value>, position: Position) -> Result<Value, String> {
+    if items.len() > MAX_ARRAY_SIZE {
+        return Err(format!("{}: more than {} array items", position, MAX_ARRAY_SIZE));
+    }
+    Ok(Value::Array(Rc::new(items)))
+}
+
+fn binary(operator: &str,
+          left: &Value,
+          right: &Value,
+          position: Position)
+          -> Result<Value, String> {
+    let mismatch = || {
+        format!("{}: cannot apply {} to {} and {}",
+                position,
+                operator,
+                left.type_name(),
+                right.type_name())
+    };
+    match (left, right) {
+        (&Value::Int(a), &Value::Int(b)) => {
+            if let Some(result) = compare(operator, a, b) {
+                return Ok(Value::Bool(result));
+            }
+            if (operator == "/" || operator == "%") && b == 0 {
+                return Err(format!("{}: division by zero", position));
+            }
+            let result = match operator {
+                "+" => a.checked_add(b),
+                "-" => a.checked_sub(b),
+                "*" => a.checked_mul(b),
+                "/" => a.checked_div(b),
+                "%" => a.checked_rem(b),
+                _ => return Err(mismatch()),
+            };
+            result.map(Value::Int).ok_or(format!("{}: int overflow", position))
+        }
+        (&Value::Str(ref a), &Value::Str(ref b)) => {
+            if let Some(result) = compare(operator, a, b) {
+                return Ok(Value::Bool(result));
+            }
+            if operator != "+" {
+                return Err(mismatch());
+            }
+            if a.len() + b.len() > MAX_STRING_SIZE {
+                return Err(format!("{}: strings longer than {} bytes",
+                                   position,
+                                   MAX_STRING_SIZE));
+            }
+            Ok(Value::Str(Rc::new(format!("{}{}", a, b))))
+        }
+        (&Value::Bool(a), &Value::Bool(b)) if operator == "==" || operator == "!=" => {
+            Ok(Value::Bool((a == b) == (operator == "==")))
+        }
+        (&Value::Array(ref a), &Value::Array(ref b)) if operator == "+" => {
+            let mut items = (**a).clone();
+            items.extend(b.iter().cloned());
+            sized_array(items, position)
+        }
+        _ => {
+            let (a, b) = match (left.as_float(), right.as_float()) {
+                (Some(a), Some(b)) => (a, b),
+                _ => return Err(mismatch()),
+            };
+            if let Some(result) = compare(operator, a, b) {
+                return Ok(Value::Bool(result));
+            }
+            match operator {
+                "+" => Ok(Value::Float(a + b)),
+                "-" => Ok(Value::Float(a - b)),
+                "*" => Ok(Value::Float(a * b)),
+                "/" => Ok(Value::Float(a / b)),
+                "%" => Ok(Value::Float(a % b)),
+                _ => Err(mismatch()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::iter::repeat;
+    use std::rc::Rc;
+
+    fn run(source: &str) -> Result<Value, String> {
+        let program = try!(Program::parse(source));
+        program.run(vec![("xs".to_string(), Value::Array(Rc::new(vec![Value::Int(3),
+                                                                      Value::Float(0.5)])))])
+               .map(|(value, _)| value)
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(run("1 + 2 * 3 - 4 / 2"), Ok(Value::Int(5)));
+        assert_eq!(run("(1 + 2) * 0.5"), Ok(Value::Float(1.5)));
+        assert_eq!(run("-2.0.abs() + max(1, 4) % 3"), Ok(Value::Float(-1.0)));
+        assert_eq!(run("7 / 2 == 3 && !(1.5 < 1) || false"), Ok(Value::Bool(true)));
+        assert_eq!(run("\"a\" + \"b\" == \"ab\""), Ok(Value::Bool(true)));
+        assert_eq!(run("len(xs + [1, 2,])"), Ok(Value::Int(4)));
+        assert_eq!(run("1e3 + 1_000 + 2.5e-1"), Ok(Value::Float(2000.25)));
+    }
+
+    #[test]
+    fn statements() {
+        let source = "let sum = 0.0;
+                      for x in xs { sum += x; }   // 3.5
+                      let i = 0;
+                      while true {
+                          i += 1;
+                          if i < 3 { continue; } else if i == 3 { sum = sum * 2.0 } else { break; }
+                      }
+                      loop { let sum = 0; break; }
+                      for i in range(0, 100) { if i == 2 { sum += i; break } }
+                      sum";
+        assert_eq!(run(source), Ok(Value::Float(9.0)));
+        assert_eq!(run("let x = 1; if x > 0 { \"positive\" } else { \"negative\" }"),
+                   Ok(Value::Str(Rc::new("positive".to_string()))));
+        assert_eq!(run("for x in xs { return x; } 0"), Ok(Value::Int(3)));
+        assert_eq!(run("1;"), Ok(Value::Unit));
+        assert_eq!(run("/* nothing */"), Ok(Value::Unit));
+    }
+
+    #[test]
+    fn scores() {
+        let program = Program::parse("scores.add(\"a\", 1); scores.add(\"b\", 0.5); 2.0").unwrap();
+        let (value, scores) = program.run(vec![("scores".to_string(), Value::Scores)]).unwrap();
+        assert_eq!(value, Value::Float(2.0));
+        assert_eq!(scores, vec![("a".to_string(), 1.0), ("b".to_string(), 0.5)]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source: &str| run(source).unwrap_err();
+        assert_eq!(error("let x = 1;\n  x +"), "line 2, column 6: expected a value, found the end");
+        assert_eq!(error("let x = 1 let y = 2"),
+                   "line 1, column 11: expected ';', found let");
+        assert_eq!(error("break"), "line 1, column 1: break outside of a loop");
+        assert_eq!(error("y = 2"), "line 1, column 1: unknown variable y");
+        assert_eq!(error("xs[2]"), "line 1, column 3: index 2 is out of bounds for an array of 2");
+        assert_eq!(error("xs.l"), "line 1, column 3: array has no property l");
+        assert_eq!(error("1 / 0"), "line 1, column 3: division by zero");
+        assert_eq!(error("if 1 { 2 }"), "line 1, column 4: expected a bool, found int");
+        assert_eq!(error("1 + true"), "line 1, column 3: cannot apply + to int and bool");
+        assert_eq!(error("sqrt(\"x\")"), "line 1, column 1: sqrt does not take (string)");
+        assert_eq!(error("foo(1)"), "line 1, column 1: unknown function foo");
+        assert_eq!(error("\"abc"), "line 1, column 1: unterminated string");
+        let repeat = |text: &str| repeat(text).take(1000).collect::<String>();
+        assert!(error(&repeat("-")).ends_with("nested too deeply"));
+        assert!(error(&format!("{}1{}", repeat("("), repeat(")"))).ends_with("nested too deeply"));
+        assert!(error(&repeat("1 + ")).ends_with("nested too deeply"));
+    }
+
+    #[test]
+    fn endless_loops_fail() {
+        assert_eq!(run("loop { }"), Err(format!("more than {} operations", MAX_OPERATIONS)));
+        assert!(run("let i = 0; while true { i = i + 1; }").is_err());
+    }
+
+    #[test]
+    fn sizes_are_limited() {
+        assert_eq!(run("let s = \"ab\"; loop { s = s + s; }"),
+                   Err(format!("line 1, column 28: strings longer than {} bytes",
+                               MAX_STRING_SIZE)));
+        assert_eq!(run("let a = [0]; loop { a = a + a; }"),
+                   Err(format!("line 1, column 27: more than {} array items", MAX_ARRAY_SIZE)));
+        assert_eq!(run("len(range(0, 100000))"),
+                   Err(format!("line 1, column 5: more than {} array items", MAX_ARRAY_SIZE)));
+        assert!(run("len(range(-9223372036854775807, 1))").is_err());
+        assert_eq!(run("len(range(0, 10000))"), Ok(Value::Int(10000)));
+        let program = Program::parse("loop { scores.add(\"x\", 1); }").unwrap();
+        assert_eq!(program.run(vec![("scores".to_string(), Value::Scores)]),
+                   Err(format!("line 1, column 14: more than {} scores", MAX_SCORES)));
+    }
+}
@@ -0,0 +1,52 @@
+//! Generates perceptually optimized color schemes by optimizing over the
+//! colors' perceived distances with genetic algorithms.
+//!
+//! A problem is a `ColorSchemeProblemDescription`: the fixed colors (e.g.
+//! backgrounds), the number of free colors to generate and the fitness
+//! `Target`s. A `Population` of `ColorScheme`s is then evolved generation by
+//! generation until the best scheme is good enough, which can be written with
+//! the `export` module.
+
+#![feature(iter_arith)]
+#![feature(slice_patterns)]
+
+pub extern crate palette;
+extern crate rand;
+extern crate stats;
+
+#[macro_use]
+pub mod color;
+pub mod cache;
+pub mod fitness;
+pub mod parse;
+pub mod script;
+pub mod interpreter;
+pub mod genetic;
+pub mod selection;
+pub mod assignment;
+pub mod crossover;
+pub mod mutation;
+pub mod optimizer;
+pub mod cmaes;
+pub mod differential;
+pub mod annealing;
+pub mod local_search;
+pub mod hall_of_fame;
+pub mod seeding;
+pub mod colorscheme;
+pub mod preview;
+pub mod diagnostics;
+pub mod history;
+pub mod benchmark;
+pub mod export;
+pub mod error;
+
+pub use fitness::{ColorSchemeProblemDescription, Target, TargetDirection, Stat, Parameter,
+                  Strength, Curve};
+pub use genetic::{Population, Individual, Genotype, ProblemDescription, Selection, Crossover,
+                  Mutation, Tournament};
+pub use colorscheme::{ColorScheme, ColorSchemePopulation};
+pub use optimizer::{Optimizer, ContinuousProblem, OptimizerKind, ColorSchemeOptimizer};
+pub use color::{distance, ciede2000, euclidean_distance, contrast_ratio};
+pub use parse::line_to_target;
+pub use error::Error;
@@ -0,0 +1,296 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use genetic::{Individual, Population, ProblemDescription, Selection, Crossover, Mutation,
+              compare_fitness, evaluate_genotype};
+use optimizer::{ContinuousProblem, clamp};
+
+/// Gradient-free local search methods. The parameter is the maximal number of
+/// fitness evaluations.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LocalSearch {
+    /// Tries a step up and down every coordinate and halves the step once no
+    /// step improves.
+    CoordinateDescent(usize),
+    NelderMead(usize),
+}
+
+impl Default for LocalSearch {
+    fn default() -> LocalSearch {
+        LocalSearch::CoordinateDescent(500)
+    }
+}
+
+impl FromStr for LocalSearch {
+    type Err = String;
+
+    /// Parses `coordinate[:budget]` or `nelder-mead[:budget]`.
+    fn from_str(name: &str) -> Result<LocalSearch, String> {
+        let mut parts = name.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let budget = match parts.next() {
+            Some(b) => {
+                try!(b.parse()
+                      .ok()
+                      .and_then(|b: usize| if b > 0 { Some(b) } else { None })
+                      .ok_or(format!("invalid budget for local search {}", name)))
+            }
+            None => 500,
+        };
+        match kind {
+            "coordinate" => Ok(LocalSearch::CoordinateDescent(budget)),
+            "nelder-mead" => Ok(LocalSearch::NelderMead(budget)),
+            _ => Err(format!("unknown local search {}, expected coordinate or nelder-mead", name)),
+        }
+    }
+}
+
+impl fmt::Display for LocalSearch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LocalSearch::CoordinateDescent(budget) => write!(f, "coordinate:{}", budget),
+            LocalSearch::NelderMead(budget) => write!(f, "nelder-mead:{}", budget),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Refinement<G> {
+    pub individual: Individual<G>,
+    /// Fitness gained by the search.
+    pub improvement: f32,
+    pub evaluations: usize,
+}
+
+/// Counts the evaluations and keeps all points within the bounds.
+struct Objective<'a, P: 'a> {
+    problem: &'a P,
+    bounds: Vec<(f32, f32)>,
+    evaluations: usize,
+}
+
+impl<'a, P: ContinuousProblem> Objective<'a, P> {
+    fn evaluate(&mut self, x: &mut Vec<f32>) -> f32 {
+        clamp(x, &self.bounds);
+        self.evaluations += 1;
+        self.problem.calculate_fitness(&self.problem.decode(x))
+    }
+}
+
+/// Searches for a fitter genotype close to the given one. The result is never
+/// worse than the start by `Individual::compare`, so the search can't trade a
+/// constraint or a priority level for total fitness.
+pub fn refine<P: ContinuousProblem>(method: LocalSearch,
+                                    problem: &P,
+                                    genotype: &P::Genotype)
+                                    -> Refinement<P::Genotype> {
+    let mut objective = Objective {
+        problem: problem,
+        bounds: problem.bounds(),
+        evaluations: 0,
+    };
+    let mut start = problem.encode(genotype);
+    let start_fitness = objective.evaluate(&mut start);
+    let (x, fitness) = match method {
+        LocalSearch::CoordinateDescent(budget) => {
+            coordinate_descent(&mut objective, start.clone(), start_fitness, budget)
+        }
+        LocalSearch::NelderMead(budget) => {
+            nelder_mead(&mut objective, start.clone(), start_fitness, budget)
+        }
+    };
+    let start = evaluate_genotype(problem, problem.decode(&start));
+    let individual = if fitness > start_fitness {
+        let refined = evaluate_genotype(problem, problem.decode(&x));
+        if refined.compare(&start) == Ordering::Less {
+            refined
+        } else {
+            start.clone()
+        }
+    } else {
+        start.clone()
+    };
+    Refinement {
+        improvement: individual.fitness() - start.fitness(),
+        individual: individual,
+        evaluations: objective.evaluations,
+    }
+}
+
+fn coordinate_descent<P: ContinuousProblem>(objective: &mut Objective<P>,
+                                            mut x: Vec<f32>,
+                                            mut fitness: f32,
+                                            budget: usize)
+                                            -> (Vec<f32>, f32) {
+    let mut step = 0.05;
+    while step > 1e-4 && objective.evaluations < budget {
+        let mut improved = false;
+        for j in 0..x.len() {
+            let (lower, upper) = objective.bounds[j];
+            for &direction in [1.0, -1.0].iter() {
+                if objective.evaluations >= budget {
+                    break;
+                }
+                let mut y = x.clone();
+                y[j] += direction * step * (upper - lower);
+                let f = objective.evaluate(&mut y);
+                if f > fitness {
+                    x = y;
+                    fitness = f;
+                    improved = true;
+                    break;
+                }
+            }
+        }
+        if !improved {
+            step /= 2.0;
+        }
+    }
+    (x, fitness)
+}
+
+fn nelder_mead<P: ContinuousProblem>(objective: &mut Objective<P>,
+                                     start: Vec<f32>,
+                                     start_fitness: f32,
+                                     budget: usize)
+                                     -> (Vec<f32>, f32) {
+    let n = start.len();
+    let mut simplex = vec![(start.clone(), start_fitness)];
+    for j in 0..n {
+        let (lower, upper) = objective.bounds[j];
+        let mut x = start.clone();
+        let step = 0.05 * (upper - lower);
+        x[j] = if x[j] + step <= upper {
+            x[j] + step
+        } else {
+            x[j] - step
+        };
+        let f = objective.evaluate(&mut x);
+        simplex.push((x, f));
+    }
+
+    // affine combination a + t * (b - a)
+    let along = |a: &[f32], b: &[f32], t: f32| -> Vec<f32> {
+        a.iter().zip(b.iter()).map(|(a, b)| a + t * (b - a)).collect()
+    };
+
+    while objective.evaluations < budget {
+        // best first
+        simplex.sort_by(|a, b| compare_fitness(a.1, b.1));
+        if (simplex[0].1 - simplex[n].1).abs() < 1e-9 {
+            break;
+        }
+        let centroid: Vec<f32> = (0..n)
+                                     .map(|j| simplex[..n].iter().map(|p| p.0[j]).sum::<f32>() /
+                                              n as f32)
+                                     .collect();
+        let (worst, worst_fitness) = simplex[n].clone();
+
+        let mut reflected = along(&centroid, &worst, -1.0);
+        let reflected_fitness = objective.evaluate(&mut reflected);
+        if reflected_fitness > simplex[0].1 {
+            let mut expanded = along(&centroid, &worst, -2.0);
+            let expanded_fitness = objective.evaluate(&mut expanded);
+            simplex[n] = if expanded_fitness > reflected_fitness {
+                (expanded, expanded_fitness)
+            } else {
+                (reflected, reflected_fitness)
+            };
+        } else if reflected_fitness > simplex[n - 1].1 {
+            simplex[n] = (reflected, reflected_fitness);
+        } else {
+            let outside = reflected_fitness > worst_fitness;
+            let mut contracted = if outside {
+                along(&centroid, &reflected, 0.5)
+            } else {
+                along(&centroid, &worst, 0.5)
+            };
+            let contracted_fitness = objective.evaluate(&mut contracted);
+            if (outside && contracted_fitness >= reflected_fitness) ||
+               (!outside && contracted_fitness > worst_fitness) {
+                simplex[n] = (contracted, contracted_fitness);
+            } else {
+                // shrink towards the best point
+                let best = simplex[0].0.clone();
+                for point in simplex.iter_mut().skip(1) {
+                    let mut x = along(&best, &point.0, 0.5);
+                    let f = objective.evaluate(&mut x);
+                    *point = (x, f);
+                }
+            }
+        }
+    }
+
+    simplex.sort_by(|a, b| compare_fitness(a.1, b.1));
+    simplex.swap_remove(0)
+}
+
+impl<P, S, C, M> Population<P, S, C, M>
+    where P: ContinuousProblem,
+          S: Selection,
+          C: Crossover<P::Genotype>,
+          M: Mutation<P::Genotype>
+{
+    /// Replaces the best individual by `Individual::compare` by its local
+    /// optimum. Returns `None` for an empty population.
+    pub fn refine_elite(&mut self, method: LocalSearch) -> Option<Refinement<P::Genotype>> {
+        self.evaluate();
+        let best = {
+            let individuals = &self.individuals;
+            (0..individuals.len()).fold(None, |best: Option<usize>, i| {
+                match best {
+                    Some(b) if individuals[b].compare(&individuals[i]) != Ordering::Greater => {
+                        best
+                    }
+                    _ => Some(i),
+                }
+            })
+        };
+        let best = match best {
+            Some(i) => i,
+            None => return None,
+        };
+        let refinement = refine(method,
+                                self.problem_description(),
+                                &self.individuals[best].genotype);
+        self.individuals[best] = refinement.individual.clone();
+        Some(refinement)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use optimizer::test::{Sphere, HalfSphere, Point};
+
+    #[test]
+    fn local_search_finds_optimum() {
+        for &method in [LocalSearch::CoordinateDescent(2000), LocalSearch::NelderMead(2000)]
+                           .iter() {
+            let refinement = refine(method, &Sphere(3), &Point(vec![-0.5, 0.9, 0.0]));
+            assert!(refinement.improvement > 0.0);
+            assert!(refinement.individual.fitness() > -1e-4,
+                    "{}: {}",
+                    method,
+                    refinement.individual.fitness());
+            assert!(refinement.evaluations <= 2000 + 4);
+        }
+    }
+
+    #[test]
+    fn never_worse_by_constraints() {
+        // the fittest point nearby violates the constraint
+        let refinement = refine(LocalSearch::CoordinateDescent(500),
+                                &HalfSphere(3),
+                                &Point(vec![-0.01, 0.3, 0.3]));
+        assert!(refinement.individual.is_feasible());
+        assert!(refinement.improvement >= 0.0);
+    }
+
+    #[test]
+    fn parse_local_search() {
+        assert_eq!("nelder-mead:100".parse(), Ok(LocalSearch::NelderMead(100)));
+        assert!("coordinate:0".parse::<LocalSearch>().is_err());
+    }
+}
@@ -1,47 +1,32 @@
-// #![allow(warnings)]
-
-#![feature(iter_arith)]
-#![feature(slice_patterns)]
-
-extern crate palette;
-extern crate rand;
 #[macro_use]
-extern crate lazy_static;
-extern crate stats;
-
-use palette::{Lab, Lch, Rgb};
-use std::ops::Add;
-use palette::Limited;
-use palette::pixel::Srgb;
+extern crate chromogene;
+extern crate rand;
 
-use rand::{thread_rng, Rng, Rand};
-use rand::distributions::{Normal, IndependentSample};
-use rand::distributions::exponential::Exp1;
+use chromogene::palette::Lab;
+use chromogene::{ColorSchemeProblemDescription, Target, ColorScheme, ColorSchemeOptimizer,
+                 Optimizer, ProblemDescription, line_to_target};
+use chromogene::genetic::Individual;
+use chromogene::{preview, diagnostics, history, benchmark, export, local_search, seeding};
+use chromogene::history::FitnessHistory;
+use chromogene::hall_of_fame::HallOfFame;
+use chromogene::fitness::Normalization;
+use chromogene::script::FitnessScript;
+use chromogene::Error;
 
-use stats::{stddev, mean};
+use rand::thread_rng;
 use std::collections::HashMap;
 
-mod fitness;
-use fitness::{ColorSchemeProblemDescription, FitnessData, StatValues};
-use fitness::Parameter::*;
-use fitness::Stat::*;
-use fitness::TargetDirection::*;
-use fitness::{Target, Strength};
-
-mod genetic;
-use genetic::{Population, Genotype, ProblemDescription};
-
-#[macro_use]
-mod color;
-#[allow(unused_imports)]
-use color::*;
-
-mod colorscheme;
-use colorscheme::ColorScheme;
+mod options;
+use options::{Options, PreviewMode, PaletteSource};
 
 use std::sync::mpsc::channel;
 use std::thread;
 use std::io;
+use std::io::{BufRead, Write};
+use std::env;
+use std::process;
+use std::time::Instant;
+use std::fs::File;
 
 enum Change {
     Target(Target),
@@ -49,131 +34,380 @@ enum Change {
     FixedColors(Vec<Lab>),
 }
 
-fn line_to_target(line: &str) -> Result<Target, String> {
-    let mut line = line.trim().split(" ");
-    let direction = try!(line.next().ok_or("expected string").and_then(|string| {
-        match string {
-            "minimize" => Ok(Minimize),
-            "maximize" => Ok(Maximize),
-            "approximate" => {
-                line.next()
-                    .ok_or("expected string")
-                    .and_then(|s| s.parse().map_err(|_| "expected float"))
-                    .map(|f| Approximate(f))
-            }
+/// Parses a target read from the given line of stdin and checks that the
+/// problem can have it.
+fn read_target(line: &str,
+               number: usize,
+               descr: &ColorSchemeProblemDescription)
+               -> Result<Target, Error> {
+    let target = try!(line_to_target(line)
+                          .map_err(|err| err.with_context(format!("line {}", number))));
+    try!(descr.check_target(&target));
+    Ok(target)
+}
 
-            _ => Err("expected minimize, maximize or approximate"),
+/// Reads all targets from stdin, for modes which don't change the problem
+/// while running.
+fn read_targets(descr: &mut ColorSchemeProblemDescription) -> Result<(), Error> {
+    let stdin = io::stdin();
+    for (i, line) in stdin.lock().lines().enumerate() {
+        let line = try!(line.map_err(|err| Error::io("could not read targets from stdin", err)));
+        if line.trim().is_empty() {
+            continue;
         }
-    }));
+        let target = try!(read_target(&line, i + 1, descr));
+        descr.set(target);
+    }
+    Ok(())
+}
 
-    let stat = try!(line.next().ok_or("expected string").and_then(|string| {
-        match string {
-            "mean" => Ok(Mean),
-            "stddev" => Ok(StdDev),
-            "min" => Ok(Min),
-            "max" => Ok(Max),
-            _ => Err("expected mean, stddev, min or max"),
-        }
-    }));
+fn write_export(scheme: &ColorScheme,
+                descr: &ColorSchemeProblemDescription,
+                path: &str,
+                format: export::ExportFormat)
+                -> Result<(), Error> {
+    File::create(path)
+        .and_then(|mut file| export::export(scheme, descr, format, &mut file))
+        .map_err(|err| {
+            Error::Export {
+                path: path.to_string(),
+                error: err,
+            }
+        })
+}
 
-    let parameter = try!(line.next().ok_or("expected string").and_then(|string| {
-        match string {
-            "chroma" => Ok(Chroma),
-            "luminance" => Ok(Luminance),
-            "freedist" => Ok(FreeDistance),
-            "fixeddist" => Ok(FixedDistance),
-            _ => Err("expected chroma, luminance, freedist or fixeddist"),
+/// Writes the best scheme to the path and the other members of the hall of
+/// fame next to it.
+fn write_exports(best: &ColorScheme,
+                 hall_of_fame: Option<&HallOfFame<ColorScheme>>,
+                 descr: &ColorSchemeProblemDescription,
+                 path: &str,
+                 format: export::ExportFormat)
+                 -> Result<(), Error> {
+    try!(write_export(best, descr, path, format));
+    if let Some(hall_of_fame) = hall_of_fame {
+        for (rank, member) in hall_of_fame.members().iter().enumerate().skip(1) {
+            try!(write_export(&member.genotype, descr, &alternative_path(path, rank + 1), format));
         }
-    }));
+    }
+    Ok(())
+}
 
-    let factor = line.next().and_then(|s| s.parse().ok()).unwrap_or(1.0);
+fn read_seed(source: &PaletteSource) -> Result<Vec<Lab>, Error> {
+    match *source {
+        PaletteSource::File(ref path) => seeding::read_palette(path),
+        PaletteSource::Colors(ref colors) => {
+            let palette = seeding::parse_hex_colors(colors);
+            if palette.is_empty() {
+                Err(Error::parse("--seed-colors", format!("no #rrggbb colors found in {}", colors)))
+            } else {
+                Ok(palette)
+            }
+        }
+    }
+}
 
-    let exponent = line.next().and_then(|s| s.parse().ok()).unwrap_or(1);
+/// `scheme.css` becomes `scheme-2.css` for the second alternative.
+fn alternative_path(path: &str, rank: usize) -> String {
+    match path.rfind('.') {
+        Some(dot) if !path[dot..].contains('/') => {
+            format!("{}-{}{}", &path[..dot], rank, &path[dot..])
+        }
+        _ => format!("{}-{}", path, rank),
+    }
+}
 
-    Ok(Target::new(direction,
-                   stat,
-                   parameter,
-                   Strength {
-                       factor: factor,
-                       exponent: exponent,
-                   }))
+fn print_preview(scheme: &ColorScheme,
+                 descr: &ColorSchemeProblemDescription,
+                 mode: PreviewMode) {
+    match mode {
+        PreviewMode::Swatches => scheme.preview(descr),
+        PreviewMode::Code(language) => preview::print_code_preview(scheme, descr, language),
+    }
 }
 
 fn main() {
-    let mut descr = ColorSchemeProblemDescription {
-        free_color_count: 6,
-        fixed_colors: vec![srgb!(0, 43, 54), srgb!(253, 246, 227)],
-        // fixed_colors: vec![srgb!(51, 51, 51)],
-        // fixed_colors: vec![srgb!(255, 255, 255)],
-        fitness_targets: HashMap::new(),
+    if let Err(err) = run() {
+        let _ = writeln!(io::stderr(), "{}", err);
+        process::exit(err.exit_code());
+    }
+}
+
+/// Errors while optimizing end the run after the best scheme so far is
+/// printed.
+fn run() -> Result<(), Error> {
+    let options = try!(Options::parse(env::args().skip(1)).map_err(Error::Usage));
+
+    let mut descr = ColorSchemeProblemDescription::new(6,
+                                                       vec![srgb!(0, 43, 54),
+                                                            srgb!(253, 246, 227)]);
+    // fixed_colors: vec![srgb!(51, 51, 51)],
+    // fixed_colors: vec![srgb!(255, 255, 255)],
+    if !options.roles.is_empty() {
+        descr.set_roles(options.roles.clone());
+    }
+    descr.cache.borrow_mut().enabled = options.fitness_cache;
+    descr.normalization = options.normalization;
+    descr.priority_tolerance = options.priority_tolerance;
+    if let Some(ref path) = options.script {
+        descr.script = Some(try!(FitnessScript::load(path)));
+    }
+    try!(descr.validate());
+
+    let mut history = match options.log {
+        Some((ref path, format)) => {
+            Some(try!(FitnessHistory::create(path, format).map_err(|err| {
+                Error::io(format!("could not create log {}", path), err)
+            })))
+        }
+        None => None,
     };
 
+    if let Some(ref grid) = options.benchmark {
+        try!(read_targets(&mut descr));
+        if let Normalization::Calibration(samples) = descr.normalization {
+            descr.calibrate(samples, &mut thread_rng());
+        }
+        let results = try!(benchmark::run_grid(grid, &descr, history.as_mut())
+                               .map_err(|err| Error::io("could not write log", err)));
+        benchmark::print_table(&results);
+        if let Some(ref path) = options.benchmark_out {
+            try!(benchmark::write_csv(&results, path)
+                     .map_err(|err| Error::io(format!("could not write {}", path), err)));
+        }
+        return Ok(());
+    }
+
     let (tx, rx) = channel();
     let stdin_thread = thread::spawn(move || {
         let mut input = String::new();
-        while io::stdin().read_line(&mut input).unwrap() > 0 {
-            tx.send(input.clone()).unwrap();
+        loop {
+            match io::stdin().read_line(&mut input) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) => {
+                    let _ = tx.send(Err(err));
+                    break;
+                }
+            }
+            // fails once the optimization has ended
+            if tx.send(Ok(input.clone())).is_err() {
+                break;
+            }
             input.clear();
         }
     });
 
 
     let generations = 50000000;
-    let population_size = 1000;
-    let runs = 1;
+    let population_size = options.optimizer.default_size();
+
+    let mut rng = thread_rng();
+    let mut p = ColorSchemeOptimizer::new(options.optimizer,
+                                          population_size,
+                                          descr.clone(),
+                                          &mut rng);
+    if let Some(ga) = p.genetic() {
+        ga.selection = options.selection;
+        ga.crossover = options.crossover();
+        ga.mutation = options.mutation;
+        ga.step_size = options.step_size;
+        ga.preservation = options.preservation;
+        ga.reseed = options.reseed;
+    }
+    if !options.seed_palettes.is_empty() {
+        let mut seeds = vec![];
+        for source in options.seed_palettes.iter() {
+            let palette = try!(read_seed(source));
+            seeds.push(seeding::scheme_from_palette(&palette, &descr, &mut rng));
+        }
+        p.seed(&seeds, options.seed_fraction, &mut rng);
+    }
 
-    // benchmark parameters:
-    // let generations = 500;
-    // let population_size = 50;
-    // let runs = 20;
+    let mut hall_of_fame = options.alternatives
+                                  .map(|(count, distance)| HallOfFame::new(count, distance));
 
-    let mut run_stats = stats::OnlineStats::new();
-    let mut run_minmax = stats::MinMax::new();
+    let start = Instant::now();
     let mut last_fitness_change = 0;
-    for run in 0..runs {
-        let mut rng = thread_rng();
-        let mut p: Population<ColorScheme, ColorSchemeProblemDescription> =
-            Population::new(population_size, descr.clone(), &mut rng);
-
-        let mut latest: Option<ColorScheme> = None;
-        for i in 0..generations {
-            if let Ok(line) = rx.try_recv() {
-                line_to_target(&line)
+    let mut latest: Option<Individual<ColorScheme>> = None;
+    let mut line_number = 0;
+    let mut failure = None;
+    for i in 0..generations {
+        match rx.try_recv() {
+            Ok(Ok(line)) => {
+                line_number += 1;
+                // a typo in a target doesn't end the run
+                read_target(&line, line_number, &descr)
                     .map(|target| {
                         descr.set(target);
-                        p.problem_description = descr.clone();
+                        if let Normalization::Calibration(samples) = descr.normalization {
+                            descr.calibrate(samples, &mut rng);
+                        }
+                        p.set_problem_description(descr.clone());
+                        if let Some(ref mut hall_of_fame) = hall_of_fame {
+                            hall_of_fame.reevaluate(&descr);
+                        }
                         last_fitness_change = i;
                     })
                     .unwrap_or_else(|err| println!("{}", err));
-            };
+            }
+            Ok(Err(err)) => {
+                failure = Some(Error::io("could not read targets from stdin", err));
+                break;
+            }
+            Err(_) => {}
+        }
+        if let Some(script) = descr.script.clone() {
+            match script.reload_if_changed() {
+                Ok(true) => {
+                    println!("reloaded {}", options.script.as_ref().map_or("script", |p| &p[..]));
+                    p.set_problem_description(descr.clone());
+                    if let Some(ref mut hall_of_fame) = hall_of_fame {
+                        hall_of_fame.reevaluate(&descr);
+                    }
+                    last_fitness_change = i;
+                }
+                Ok(false) => {}
+                Err(err) => println!("could not reload script, keeping the old one: {}", err),
+            }
+        }
+
+        let heat = options.heat.heat(i - last_fitness_change);
+        let generation = p.step(heat, &mut rng);
+        latest = Some(generation.best.clone());
+        if let Some((method, every)) = options.local_search {
+            if i > 0 && i % every == 0 {
+                if let Some(refinement) = p.genetic().and_then(|ga| ga.refine_elite(method)) {
+                    println!("local search improved the elite by {:.5} in {} evaluations",
+                             refinement.improvement,
+                             refinement.evaluations);
+                }
+            }
+        }
+        if let Some(ref mut hall_of_fame) = hall_of_fame {
+            hall_of_fame.update(&generation.evaluated);
+        }
+        if descr.normalization == Normalization::Population {
+            let schemes: Vec<&ColorScheme> = generation.evaluated
+                                                       .iter()
+                                                       .map(|i| &i.genotype)
+                                                       .collect();
+            // in place, so that the search state and the fitness cache are kept
+            p.update_problem(|d| d.normalize(&schemes));
+            descr.set_scales(&p.problem_description().scales());
+            if let Some(ref mut hall_of_fame) = hall_of_fame {
+                hall_of_fame.reevaluate(&descr);
+            }
+        }
+        let undefined = generation.evaluated.iter().filter(|i| i.fitness().is_nan()).count();
+        if let Some(individual) = generation.evaluated.iter().find(|i| i.fitness().is_nan()) {
+            println!("{} schemes have an undefined (NaN) fitness and rank last, caused by: {}",
+                     undefined,
+                     descr.nan_targets(&individual.genotype).join(", "));
+        }
+        let best = &generation.best.genotype;
+        if let Some(err) = descr.script.as_ref().and_then(|script| script.take_error()) {
+            println!("{}", err);
+        }
 
-            let heat = (1.0 - (i - last_fitness_change) as f32 / 200 as f32).powi(1).max(0.01);
-            let stats = p.next_generation(heat, &mut rng);
-
-            // if generations < 100 || i % (generations / 100) == 0 {
-            stats.0.preview(&descr);
-            stats.0.print_fitness(&descr);
-            println!("{:04}: best fitness: {:11.5}, avg: {:6.2}, sd: {:6.2}  heat: {:5.3}\n",
-                     i,
-                     stats.0.get_fitness(),
-                     stats.1,
-                     stats.2,
-                     heat);
-            // }
-
-            latest = Some(stats.0);
-        }
-        let best = latest.unwrap();
-        best.preview(&descr);
-        run_stats.add(best.get_fitness());
-        run_minmax.add(best.get_fitness());
-        println!("{:8.3}", best.get_fitness());
+        // if generations < 100 || i % (generations / 100) == 0 {
+        print_preview(best, &descr, options.preview);
         best.print_fitness(&descr);
+        if options.diagnostics {
+            diagnostics::print_diagnostics(best, &descr, options.worst_pairs);
+        }
+        let diversity = p.diversity();
+        if generation.reseeded > 0 {
+            println!("diversity collapsed, re-seeded {} individuals", generation.reseeded);
+        }
+        println!("{:04}: best fitness: {:11.5}, avg: {:6.2}, sd: {:6.2}  heat: {:5.3}  \
+                  diversity: {:5.3}\n",
+                 i,
+                 generation.best.fitness(),
+                 generation.mean_fitness,
+                 generation.sd_fitness,
+                 heat,
+                 diversity);
+        // }
+
+        if let Some(ref mut history) = history {
+            let data = best.fitness_data(&descr);
+            let elapsed = start.elapsed();
+            let record = history::Record {
+                run: 0,
+                generation: i,
+                best_fitness: generation.best.fitness(),
+                mean_fitness: generation.mean_fitness,
+                sd_fitness: generation.sd_fitness,
+                heat: heat,
+                targets: descr.fitness_targets
+                              .values()
+                              .map(|t| (t.name(), t.value(&data).unwrap_or(std::f32::NAN)))
+                              .collect(),
+                diversity: diversity,
+                time: elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9,
+            };
+            if let Err(err) = history.record(&record) {
+                failure = Some(Error::io("could not write log", err));
+                break;
+            }
+        }
+
+        if let Some((ref path, format)) = options.export {
+            if let Err(err) = write_exports(best, hall_of_fame.as_ref(), &descr, path, format) {
+                failure = Some(err);
+                break;
+            }
+        }
+    }
+    let mut best = match latest {
+        Some(best) => best,
+        None => return failure.map_or(Ok(()), Err),
+    };
+    if let Some((method, _)) = options.local_search {
+        let refinement = local_search::refine(method, &descr, &best.genotype);
+        println!("local search improved the result by {:.5} in {} evaluations",
+                 refinement.improvement,
+                 refinement.evaluations);
+        best = refinement.individual;
+        // the export and the alternatives show the refined scheme as well
+        if let Some(ref mut hall_of_fame) = hall_of_fame {
+            hall_of_fame.update(&[best.clone()]);
+        }
+        match options.export {
+            Some((ref path, format)) if failure.is_none() => {
+                failure = write_exports(&best.genotype, hall_of_fame.as_ref(), &descr, path, format)
+                              .err();
+            }
+            _ => {}
+        }
+    }
+    print_preview(&best.genotype, &descr, options.preview);
+    println!("{:8.3}", best.fitness());
+    best.genotype.print_fitness(&descr);
+    if descr.fitness_targets.values().any(|t| t.required) {
+        let violation = descr.constraint_violation(&best.genotype);
+        if violation > 0.0 {
+            println!("no feasible scheme found, constraint violation: {:.3}", violation);
+        } else {
+            println!("feasible scheme found");
+        }
+    }
+    if options.diagnostics {
+        diagnostics::print_diagnostics(&best.genotype, &descr, options.worst_pairs);
+    }
+    if let Some(ref hall_of_fame) = hall_of_fame {
+        for (rank, member) in hall_of_fame.members().iter().enumerate() {
+            println!("alternative {}: {:8.3}", rank + 1, member.fitness());
+            print_preview(&member.genotype, &descr, options.preview);
+        }
     }
-    println!("\nbest: {:8.3}\navg:  {:8.3}\nsd:   {:8.3}",
-             run_minmax.max().unwrap(),
-             run_stats.mean(),
-             run_stats.stddev());
 
-    stdin_thread.join().unwrap();
+    if let Some(err) = failure {
+        return Err(err);
+    }
+    stdin_thread.join().map_err(|_| {
+        Error::io("could not read targets from stdin",
+                  io::Error::new(io::ErrorKind::Other, "the reader thread panicked"))
+    })
 }
@@ -0,0 +1,166 @@
+use palette::{Lab, Lch, LabHue};
+use rand::Rng;
+use rand::distributions::{Normal, IndependentSample};
+use std::fmt;
+use std::str::FromStr;
+
+use colorscheme::ColorScheme;
+use genetic::Mutation;
+
+/// How a single color is perturbed. The standard deviations are scaled by the
+/// mutation strength.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MutationOperator {
+    /// Gaussian noise with a standard deviation of 0.02 on L, a and b.
+    Lab,
+    /// A gaussian step in lightness, chroma and hue with the given standard
+    /// deviations in L (0 to 100), chroma (roughly 0 to 128) and degrees.
+    /// Unlike `Lab` this can e.g. change the hue of a color while keeping
+    /// its saturation.
+    Lch {
+        lightness: f32,
+        chroma: f32,
+        hue: f32,
+    },
+}
+
+impl Default for MutationOperator {
+    fn default() -> MutationOperator {
+        MutationOperator::Lab
+    }
+}
+
+impl MutationOperator {
+    pub fn lch() -> MutationOperator {
+        MutationOperator::Lch {
+            lightness: 2.0,
+            chroma: 2.5,
+            hue: 5.0,
+        }
+    }
+}
+
+impl FromStr for MutationOperator {
+    type Err = String;
+
+    /// Parses `lab`, `lch` or `lch:LIGHTNESS:CHROMA:HUE`.
+    fn from_str(name: &str) -> Result<MutationOperator, String> {
+        let mut parts = name.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let parameters = parts.next();
+        match (kind, parameters) {
+            ("lab", None) => Ok(MutationOperator::Lab),
+            ("lch", None) => Ok(MutationOperator::lch()),
+            ("lch", Some(parameters)) => {
+                let sigmas: Vec<f32> = try!(parameters.split(':')
+                                                      .map(|p| p.trim().parse())
+                                                      .collect::<Result<_, _>>()
+                                                      .map_err(|_| {
+                                                          format!("invalid parameters for \
+                                                                   mutation {}",
+                                                                  name)
+                                                      }));
+                match sigmas[..] {
+                    [lightness, chroma, hue] if sigmas.iter().all(|&s| s >= 0.0) => {
+                        Ok(MutationOperator::Lch {
+                            lightness: lightness,
+                            chroma: chroma,
+                            hue: hue,
+                        })
+                    }
+                    _ => Err(format!("expected three non-negative deviations in {}", name)),
+                }
+            }
+            _ => Err(format!("unknown mutation {}, expected lab or lch", name)),
+        }
+    }
+}
+
+impl fmt::Display for MutationOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MutationOperator::Lab => write!(f, "lab"),
+            MutationOperator::Lch { lightness, chroma, hue } => {
+                write!(f, "lch:{}:{}:{}", lightness, chroma, hue)
+            }
+        }
+    }
+}
+
+fn gaussian<R: Rng>(sd: f32, rng: &mut R) -> f32 {
+    if sd > 0.0 {
+        Normal::new(0.0, sd as f64).ind_sample(rng) as f32
+    } else {
+        0.0
+    }
+}
+
+fn mutate_color<R: Rng>(operator: MutationOperator, color: &Lab, strength: f32, rng: &mut R)
+                        -> Lab {
+    let new = match operator {
+        MutationOperator::Lab => {
+            Lab::new(color.l + gaussian(0.02 * strength, rng),
+                     color.a + gaussian(0.02 * strength, rng),
+                     color.b + gaussian(0.02 * strength, rng))
+        }
+        MutationOperator::Lch { lightness, chroma, hue } => {
+            let lch: Lch = (*color).into();
+            let degrees = lch.hue.to_positive_degrees() + gaussian(hue * strength, rng);
+            let step = Lch::new(lch.l + gaussian(lightness / 100.0 * strength, rng),
+                                (lch.chroma + gaussian(chroma / 128.0 * strength, rng)).max(0.0),
+                                LabHue::from(degrees));
+            step.into()
+        }
+    };
+    // `max` and `min` return the bound for NaN, so this is always in bounds
+    Lab::new(new.l.max(0.0).min(1.0),
+             new.a.max(-1.0).min(1.0),
+             new.b.max(-1.0).min(1.0))
+}
+
+impl Mutation<ColorScheme> for MutationOperator {
+    fn mutate<R: Rng>(&self, scheme: &ColorScheme, strength: f32, rng: &mut R) -> ColorScheme {
+        ColorScheme::new(scheme.free_colors
+                               .iter()
+                               .map(|color| mutate_color(*self, color, strength, rng))
+                               .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use palette::{Lab, Lch};
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn hue_step_keeps_lightness_and_chroma() {
+        let seed: &[usize] = &[5];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let operator = MutationOperator::Lch {
+            lightness: 0.0,
+            chroma: 0.0,
+            hue: 30.0,
+        };
+        let color = Lab::new(0.6, 0.3, -0.2);
+        let before: Lch = color.into();
+        for _ in 0..20 {
+            let after: Lch = mutate_color(operator, &color, 1.0, &mut rng).into();
+            assert!((after.l - before.l).abs() < 1e-4);
+            assert!((after.chroma - before.chroma).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn parse_mutation() {
+        assert_eq!("lab".parse(), Ok(MutationOperator::Lab));
+        assert_eq!("lch:1:2:3".parse(),
+                   Ok(MutationOperator::Lch {
+                       lightness: 1.0,
+                       chroma: 2.0,
+                       hue: 3.0,
+                   }));
+        assert!("lch:1:2".parse::<MutationOperator>().is_err());
+        assert!("cauchy".parse::<MutationOperator>().is_err());
+    }
+}
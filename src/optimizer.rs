@@ -0,0 +1,364 @@
+use rand::Rng;
+use rand::distributions::normal::StandardNormal;
+use std::fmt;
+use std::str::FromStr;
+
+use genetic::{Population, ProblemDescription, Generation, Selection, Crossover, Mutation};
+use fitness::ColorSchemeProblemDescription;
+use colorscheme::{ColorScheme, ColorSchemePopulation};
+use cmaes::CmaEs;
+use differential::DifferentialEvolution;
+use annealing::Annealing;
+
+/// Common interface of all search methods, so they can be exchanged and
+/// compared.
+pub trait Optimizer {
+    type Problem: ProblemDescription;
+
+    fn problem_description(&self) -> &Self::Problem;
+    /// Replaces the problem. The search continues from where it is.
+    fn set_problem_description(&mut self, problem_description: Self::Problem);
+    /// Changes the problem in place, for small changes like new scales of
+    /// the targets. Unlike `set_problem_description` it keeps the adapted
+    /// state of the search, only the fitness is calculated again.
+    fn update_problem<F: FnOnce(&mut Self::Problem)>(&mut self, update: F);
+    /// One iteration. `heat` is 1 after a change of the problem and decreases
+    /// towards 0; methods which adapt their own step size may ignore it.
+    fn step<R: Rng>(&mut self,
+                    heat: f32,
+                    rng: &mut R)
+                    -> Generation<<Self::Problem as ProblemDescription>::Genotype>;
+    fn diversity(&self) -> f32;
+    /// Starts the search from the given genotypes. Population based methods
+    /// replace the given fraction of their members by them and perturbations
+    /// of them, CMA-ES and simulated annealing move to the first one.
+    fn seed<R: Rng>(&mut self,
+                    genotypes: &[<Self::Problem as ProblemDescription>::Genotype],
+                    fraction: f32,
+                    rng: &mut R);
+}
+
+/// A problem whose genotypes are points in a box of real coordinates.
+pub trait ContinuousProblem: ProblemDescription {
+    /// Lower and upper bound of every coordinate.
+    fn bounds(&self) -> Vec<(f32, f32)>;
+    fn encode(&self, genotype: &Self::Genotype) -> Vec<f32>;
+    /// Coordinates outside of the bounds are clamped.
+    fn decode(&self, x: &[f32]) -> Self::Genotype;
+}
+
+/// Clamps every coordinate into its bounds.
+pub fn clamp(x: &mut [f32], bounds: &[(f32, f32)]) {
+    for (x, &(lower, upper)) in x.iter_mut().zip(bounds.iter()) {
+        *x = x.max(lower).min(upper);
+    }
+}
+
+/// Adds gaussian noise with a standard deviation of `size` times the range of
+/// every coordinate, and clamps the result.
+pub fn perturb<R: Rng>(x: &[f32], bounds: &[(f32, f32)], size: f32, rng: &mut R) -> Vec<f32> {
+    let mut perturbed: Vec<f32> = x.iter()
+                                   .zip(bounds.iter())
+                                   .map(|(&x, &(lower, upper))| {
+                                       let StandardNormal(n) = rng.gen::<StandardNormal>();
+                                       x + n as f32 * size * (upper - lower)
+                                   })
+                                   .collect();
+    clamp(&mut perturbed, bounds);
+    perturbed
+}
+
+impl<P, S, C, M> Optimizer for Population<P, S, C, M>
+    where P: ProblemDescription,
+          S: Selection,
+          C: Crossover<P::Genotype>,
+          M: Mutation<P::Genotype>
+{
+    type Problem = P;
+
+    fn problem_description(&self) -> &P {
+        Population::problem_description(self)
+    }
+
+    fn set_problem_description(&mut self, problem_description: P) {
+        Population::set_problem_description(self, problem_description)
+    }
+
+    fn update_problem<F: FnOnce(&mut P)>(&mut self, update: F) {
+        Population::update_problem(self, update)
+    }
+
+    fn step<R: Rng>(&mut self, heat: f32, rng: &mut R) -> Generation<P::Genotype> {
+        self.next_generation(heat, rng)
+    }
+
+    fn diversity(&self) -> f32 {
+        Population::diversity(self)
+    }
+
+    fn seed<R: Rng>(&mut self, genotypes: &[P::Genotype], fraction: f32, rng: &mut R) {
+        Population::seed(self, genotypes, fraction, rng)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OptimizerKind {
+    Genetic,
+    CmaEs,
+    /// DE/rand/1/bin with differential weight and crossover probability.
+    DifferentialEvolution(f32, f32),
+    Annealing,
+}
+
+impl Default for OptimizerKind {
+    fn default() -> OptimizerKind {
+        OptimizerKind::Genetic
+    }
+}
+
+impl OptimizerKind {
+    /// Population size, offspring or moves per step for the command line
+    /// frontend. 0 lets CMA-ES use its recommended minimum.
+    pub fn default_size(&self) -> usize {
+        match *self {
+            OptimizerKind::Genetic => 1000,
+            OptimizerKind::CmaEs => 0,
+            OptimizerKind::DifferentialEvolution(_, _) => 60,
+            OptimizerKind::Annealing => 100,
+        }
+    }
+}
+
+impl FromStr for OptimizerKind {
+    type Err = String;
+
+    /// Parses `ga`, `cmaes`, `de[:F:CR]` or `sa`.
+    fn from_str(name: &str) -> Result<OptimizerKind, String> {
+        let parts: Vec<&str> = name.split(':').collect();
+        let invalid = || format!("invalid parameters for optimizer {}", name);
+        match &parts[..] {
+            ["ga"] => Ok(OptimizerKind::Genetic),
+            ["cmaes"] => Ok(OptimizerKind::CmaEs),
+            ["de"] => Ok(OptimizerKind::DifferentialEvolution(0.5, 0.9)),
+            ["de", f, cr] => {
+                match (f.parse::<f32>(), cr.parse::<f32>()) {
+                    (Ok(f), Ok(cr)) if f > 0.0 && cr >= 0.0 && cr <= 1.0 => {
+                        Ok(OptimizerKind::DifferentialEvolution(f, cr))
+                    }
+                    _ => Err(invalid()),
+                }
+            }
+            ["sa"] => Ok(OptimizerKind::Annealing),
+            _ => Err(format!("unknown optimizer {}, expected ga, cmaes, de or sa", name)),
+        }
+    }
+}
+
+impl fmt::Display for OptimizerKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OptimizerKind::Genetic => write!(f, "ga"),
+            OptimizerKind::CmaEs => write!(f, "cmaes"),
+            OptimizerKind::DifferentialEvolution(weight, rate) => {
+                write!(f, "de:{}:{}", weight, rate)
+            }
+            OptimizerKind::Annealing => write!(f, "sa"),
+        }
+    }
+}
+
+/// Any of the optimizers on color schemes.
+pub enum ColorSchemeOptimizer {
+    Genetic(ColorSchemePopulation),
+    CmaEs(CmaEs<ColorSchemeProblemDescription>),
+    DifferentialEvolution(DifferentialEvolution<ColorSchemeProblemDescription>),
+    Annealing(Annealing<ColorSchemeProblemDescription>),
+}
+
+impl ColorSchemeOptimizer {
+    /// `size` is the population size, the number of offspring per generation
+    /// for CMA-ES or the number of moves per step for simulated annealing.
+    pub fn new<R: Rng>(kind: OptimizerKind,
+                       size: usize,
+                       problem_description: ColorSchemeProblemDescription,
+                       rng: &mut R)
+                       -> ColorSchemeOptimizer {
+        match kind {
+            OptimizerKind::Genetic => {
+                ColorSchemeOptimizer::Genetic(ColorSchemePopulation::new(size,
+                                                                         problem_description,
+                                                                         rng))
+            }
+            OptimizerKind::CmaEs => {
+                ColorSchemeOptimizer::CmaEs(CmaEs::new(size, problem_description, rng))
+            }
+            OptimizerKind::DifferentialEvolution(weight, rate) => {
+                let mut de = DifferentialEvolution::new(size, problem_description, rng);
+                de.differential_weight = weight;
+                de.crossover_probability = rate;
+                ColorSchemeOptimizer::DifferentialEvolution(de)
+            }
+            OptimizerKind::Annealing => {
+                ColorSchemeOptimizer::Annealing(Annealing::new(size, problem_description, rng))
+            }
+        }
+    }
+
+    /// The genetic algorithm, to set its operators.
+    pub fn genetic(&mut self) -> Option<&mut ColorSchemePopulation> {
+        match *self {
+            ColorSchemeOptimizer::Genetic(ref mut p) => Some(p),
+            _ => None,
+        }
+    }
+}
+
+impl Optimizer for ColorSchemeOptimizer {
+    type Problem = ColorSchemeProblemDescription;
+
+    fn problem_description(&self) -> &ColorSchemeProblemDescription {
+        match *self {
+            ColorSchemeOptimizer::Genetic(ref o) => o.problem_description(),
+            ColorSchemeOptimizer::CmaEs(ref o) => o.problem_description(),
+            ColorSchemeOptimizer::DifferentialEvolution(ref o) => o.problem_description(),
+            ColorSchemeOptimizer::Annealing(ref o) => o.problem_description(),
+        }
+    }
+
+    fn set_problem_description(&mut self, descr: ColorSchemeProblemDescription) {
+        match *self {
+            ColorSchemeOptimizer::Genetic(ref mut o) => o.set_problem_description(descr),
+            ColorSchemeOptimizer::CmaEs(ref mut o) => o.set_problem_description(descr),
+            ColorSchemeOptimizer::DifferentialEvolution(ref mut o) => {
+                o.set_problem_description(descr)
+            }
+            ColorSchemeOptimizer::Annealing(ref mut o) => o.set_problem_description(descr),
+        }
+    }
+
+    fn update_problem<F: FnOnce(&mut ColorSchemeProblemDescription)>(&mut self, update: F) {
+        match *self {
+            ColorSchemeOptimizer::Genetic(ref mut o) => Optimizer::update_problem(o, update),
+            ColorSchemeOptimizer::CmaEs(ref mut o) => o.update_problem(update),
+            ColorSchemeOptimizer::DifferentialEvolution(ref mut o) => o.update_problem(update),
+            ColorSchemeOptimizer::Annealing(ref mut o) => o.update_problem(update),
+        }
+    }
+
+    fn step<R: Rng>(&mut self, heat: f32, rng: &mut R) -> Generation<ColorScheme> {
+        match *self {
+            ColorSchemeOptimizer::Genetic(ref mut o) => Optimizer::step(o, heat, rng),
+            ColorSchemeOptimizer::CmaEs(ref mut o) => o.step(heat, rng),
+            ColorSchemeOptimizer::DifferentialEvolution(ref mut o) => o.step(heat, rng),
+            ColorSchemeOptimizer::Annealing(ref mut o) => o.step(heat, rng),
+        }
+    }
+
+    fn diversity(&self) -> f32 {
+        match *self {
+            ColorSchemeOptimizer::Genetic(ref o) => o.diversity(),
+            ColorSchemeOptimizer::CmaEs(ref o) => o.diversity(),
+            ColorSchemeOptimizer::DifferentialEvolution(ref o) => o.diversity(),
+            ColorSchemeOptimizer::Annealing(ref o) => o.diversity(),
+        }
+    }
+
+    fn seed<R: Rng>(&mut self, genotypes: &[ColorScheme], fraction: f32, rng: &mut R) {
+        match *self {
+            ColorSchemeOptimizer::Genetic(ref mut o) => o.seed(genotypes, fraction, rng),
+            ColorSchemeOptimizer::CmaEs(ref mut o) => o.seed(genotypes, fraction, rng),
+            ColorSchemeOptimizer::DifferentialEvolution(ref mut o) => {
+                o.seed(genotypes, fraction, rng)
+            }
+            ColorSchemeOptimizer::Annealing(ref mut o) => o.seed(genotypes, fraction, rng),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use genetic::{Genotype, ProblemDescription};
+    use rand::Rng;
+
+    #[derive(Clone, Debug)]
+    pub struct Point(pub Vec<f32>);
+
+    impl Genotype for Point {
+        fn distance(&self, other: &Point) -> f32 {
+            self.0.iter().zip(other.0.iter()).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt()
+        }
+    }
+
+    /// Maximum of 0 at (0.3, 0.3, ...) in [-1, 1]^n.
+    pub struct Sphere(pub usize);
+
+    impl ProblemDescription for Sphere {
+        type Genotype = Point;
+
+        fn random_genotype<R: Rng>(&self, rng: &mut R) -> Point {
+            Point((0..self.0).map(|_| rng.gen_range(-1.0, 1.0)).collect())
+        }
+
+        fn calculate_fitness(&self, point: &Point) -> f32 {
+            -point.0.iter().map(|x| (x - 0.3) * (x - 0.3)).sum::<f32>()
+        }
+    }
+
+    impl ContinuousProblem for Sphere {
+        fn bounds(&self) -> Vec<(f32, f32)> {
+            vec![(-1.0, 1.0); self.0]
+        }
+
+        fn encode(&self, point: &Point) -> Vec<f32> {
+            point.0.clone()
+        }
+
+        fn decode(&self, x: &[f32]) -> Point {
+            let mut x = x.to_vec();
+            clamp(&mut x, &self.bounds());
+            Point(x)
+        }
+    }
+
+    /// `Sphere` with the constraint x[0] <= 0, so the optimum is infeasible.
+    pub struct HalfSphere(pub usize);
+
+    impl ProblemDescription for HalfSphere {
+        type Genotype = Point;
+
+        fn random_genotype<R: Rng>(&self, rng: &mut R) -> Point {
+            Sphere(self.0).random_genotype(rng)
+        }
+
+        fn calculate_fitness(&self, point: &Point) -> f32 {
+            Sphere(self.0).calculate_fitness(point)
+        }
+
+        fn constraint_violation(&self, point: &Point) -> f32 {
+            point.0[0].max(0.0)
+        }
+    }
+
+    impl ContinuousProblem for HalfSphere {
+        fn bounds(&self) -> Vec<(f32, f32)> {
+            Sphere(self.0).bounds()
+        }
+
+        fn encode(&self, point: &Point) -> Vec<f32> {
+            Sphere(self.0).encode(point)
+        }
+
+        fn decode(&self, x: &[f32]) -> Point {
+            Sphere(self.0).decode(x)
+        }
+    }
+
+    #[test]
+    fn parse_optimizer() {
+        assert_eq!("cmaes".parse(), Ok(OptimizerKind::CmaEs));
+        assert_eq!("de:0.7:0.3".parse(), Ok(OptimizerKind::DifferentialEvolution(0.7, 0.3)));
+        assert!("de:0.7".parse::<OptimizerKind>().is_err());
+        assert!("pso".parse::<OptimizerKind>().is_err());
+    }
+}
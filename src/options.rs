@@ -0,0 +1,425 @@
+use chromogene::preview::Language;
+use chromogene::history::LogFormat;
+use chromogene::benchmark::Grid;
+use chromogene::export::ExportFormat;
+use chromogene::selection::SelectionStrategy;
+use chromogene::crossover::{CrossoverOperator, Matching, Blend};
+use chromogene::mutation::MutationOperator;
+use chromogene::genetic::{HeatSchedule, StepSize, Preservation};
+use chromogene::optimizer::OptimizerKind;
+use chromogene::local_search::LocalSearch;
+use chromogene::fitness::Normalization;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PreviewMode {
+    Swatches,
+    Code(Language),
+}
+
+/// Where a palette to seed the population with comes from.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PaletteSource {
+    File(String),
+    /// Hex colors given on the command line.
+    Colors(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub preview: PreviewMode,
+    pub diagnostics: bool,
+    pub worst_pairs: usize,
+    pub optimizer: OptimizerKind,
+    pub selection: SelectionStrategy,
+    pub matching: Option<Matching>,
+    pub blend: Option<Blend>,
+    pub mutation: MutationOperator,
+    pub step_size: StepSize,
+    pub heat: HeatSchedule,
+    pub preservation: Preservation,
+    /// Diversity threshold and fraction of the population to re-seed.
+    pub reseed: Option<(f32, f32)>,
+    pub seed_palettes: Vec<PaletteSource>,
+    /// Fraction of the population started from the palettes.
+    pub seed_fraction: f32,
+    /// Number of distinct best schemes to keep and their minimal distance.
+    pub alternatives: Option<(usize, f32)>,
+    /// Refines the best scheme of the genetic algorithm every given number of
+    /// generations, and the final result. The other optimizers have no elite,
+    /// so only their final result is refined.
+    pub local_search: Option<(LocalSearch, usize)>,
+    /// Names of the free colors.
+    pub roles: Vec<String>,
+    /// Memoizes color features and distances during fitness evaluation.
+    pub fitness_cache: bool,
+    pub normalization: Normalization,
+    /// Differences of a priority level up to this count as ties.
+    pub priority_tolerance: f32,
+    /// A script whose score is added to the fitness.
+    pub script: Option<String>,
+    pub log: Option<(String, LogFormat)>,
+    /// Where to write the best scheme after every generation.
+    pub export: Option<(String, ExportFormat)>,
+    pub benchmark: Option<Grid>,
+    /// Where to write the benchmark results as csv.
+    pub benchmark_out: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            preview: PreviewMode::Swatches,
+            diagnostics: false,
+            worst_pairs: 3,
+            optimizer: OptimizerKind::default(),
+            selection: SelectionStrategy::default(),
+            matching: None,
+            blend: None,
+            mutation: MutationOperator::default(),
+            step_size: StepSize::default(),
+            heat: HeatSchedule::default(),
+            preservation: Preservation::default(),
+            reseed: None,
+            seed_palettes: vec![],
+            seed_fraction: 0.5,
+            alternatives: None,
+            local_search: None,
+            roles: vec![],
+            fitness_cache: true,
+            normalization: Normalization::None,
+            priority_tolerance: 0.0,
+            script: None,
+            log: None,
+            export: None,
+            benchmark: None,
+            benchmark_out: None,
+        }
+    }
+}
+
+impl Options {
+    pub fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut language = Language::Rust;
+        let mut code_preview = false;
+        let mut log_format = None;
+        let mut export_format = None;
+        let mut benchmark = false;
+        let mut grid = Grid::default();
+        let mut grid_changed = false;
+        let mut grid_mutations = false;
+        let mut grid_optimizers = false;
+        let mut local_search = None;
+        let mut alternatives = None;
+        let mut min_distance = None;
+        let mut refine_every = None;
+        let mut grid_step_sizes = false;
+        let mut grid_fitness_caches = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_ref() {
+                "--preview" => {
+                    code_preview = match try!(value(&mut args, &arg)).as_ref() {
+                        "swatches" => false,
+                        "code" => true,
+                        other => {
+                            return Err(format!("unknown preview mode {}, expected swatches or \
+                                                code",
+                                               other))
+                        }
+                    }
+                }
+                "--language" => {
+                    language = try!(Language::from_name(&try!(value(&mut args, &arg))));
+                }
+                "--diagnostics" => options.diagnostics = true,
+                "--worst" => {
+                    options.worst_pairs = try!(parse_value(&mut args, &arg));
+                }
+                "--log" => {
+                    let path = try!(value(&mut args, &arg));
+                    let format = LogFormat::from_path(&path);
+                    options.log = Some((path, format));
+                }
+                "--log-format" => {
+                    log_format = Some(try!(LogFormat::from_name(&try!(value(&mut args, &arg)))));
+                }
+                "--export" => {
+                    let path = try!(value(&mut args, &arg));
+                    let format = ExportFormat::from_path(&path);
+                    options.export = Some((path, format));
+                }
+                "--export-format" => {
+                    export_format =
+                        Some(try!(ExportFormat::from_name(&try!(value(&mut args, &arg)))));
+                }
+                "--benchmark" => benchmark = true,
+                "--benchmark-out" => {
+                    options.benchmark_out = Some(try!(value(&mut args, &arg)));
+                    grid_changed = true;
+                }
+                "--populations" => {
+                    grid.population_sizes = try!(parse_counts(&mut args, &arg));
+                    grid_changed = true;
+                }
+                "--generations" => {
+                    grid.generations = try!(parse_counts(&mut args, &arg));
+                    grid_changed = true;
+                }
+                "--optimizer" => options.optimizer = try!(parse_value(&mut args, &arg)),
+                "--optimizers" => {
+                    grid.optimizers = try!(parse_list(&mut args, &arg));
+                    grid_optimizers = true;
+                    grid_changed = true;
+                }
+                "--selection" => {
+                    options.selection = try!(parse_value(&mut args, &arg));
+                }
+                "--matching" => options.matching = Some(try!(parse_value(&mut args, &arg))),
+                "--crossover" => options.blend = Some(try!(parse_value(&mut args, &arg))),
+                "--roles" => options.roles = try!(parse_list(&mut args, &arg)),
+                "--mutation" => options.mutation = try!(parse_value(&mut args, &arg)),
+                "--step-size" => options.step_size = try!(parse_value(&mut args, &arg)),
+                "--heat" => options.heat = try!(parse_value(&mut args, &arg)),
+                "--niching" => options.preservation = try!(parse_value(&mut args, &arg)),
+                "--reseed" => {
+                    let string = try!(value(&mut args, &arg));
+                    let parts: Vec<f32> = try!(string.split(':')
+                                                     .map(|p| p.parse())
+                                                     .collect::<Result<_, _>>()
+                                                     .map_err(|_| {
+                                                         format!("invalid value {} for {}",
+                                                                 string,
+                                                                 arg)
+                                                     }));
+                    options.reseed = match parts[..] {
+                        [threshold] => Some((threshold, 0.5)),
+                        [threshold, fraction] if fraction > 0.0 && fraction <= 1.0 => {
+                            Some((threshold, fraction))
+                        }
+                        _ => return Err(format!("invalid value {} for {}", string, arg)),
+                    };
+                }
+                "--seed-palette" => {
+                    options.seed_palettes.push(PaletteSource::File(try!(value(&mut args, &arg))));
+                }
+                "--seed-colors" => {
+                    options.seed_palettes.push(PaletteSource::Colors(try!(value(&mut args, &arg))));
+                }
+                "--seed-fraction" => {
+                    let fraction: f32 = try!(parse_value(&mut args, &arg));
+                    if fraction < 0.0 || fraction > 1.0 {
+                        return Err("--seed-fraction must be between 0 and 1".to_string());
+                    }
+                    options.seed_fraction = fraction;
+                }
+                "--alternatives" => alternatives = Some(try!(parse_value(&mut args, &arg))),
+                "--min-distance" => min_distance = Some(try!(parse_value(&mut args, &arg))),
+                "--local-search" => local_search = Some(try!(parse_value(&mut args, &arg))),
+                "--refine-every" => {
+                    let every: usize = try!(parse_value(&mut args, &arg));
+                    if every == 0 {
+                        return Err("--refine-every must be positive".to_string());
+                    }
+                    refine_every = Some(every);
+                }
+                "--no-fitness-cache" => options.fitness_cache = false,
+                "--normalize" => options.normalization = try!(parse_value(&mut args, &arg)),
+                "--priority-tolerance" => {
+                    let tolerance: f32 = try!(parse_value(&mut args, &arg));
+                    if !(tolerance >= 0.0) {
+                        return Err("--priority-tolerance must not be negative".to_string());
+                    }
+                    options.priority_tolerance = tolerance;
+                }
+                "--script" => options.script = Some(try!(value(&mut args, &arg))),
+                "--fitness-caches" => {
+                    grid.fitness_caches = try!(parse_list(&mut args, &arg));
+                    grid_fitness_caches = true;
+                    grid_changed = true;
+                }
+                "--selections" => {
+                    grid.selections = try!(parse_list(&mut args, &arg));
+                    grid_changed = true;
+                }
+                "--mutations" => {
+                    grid.mutations = try!(parse_list(&mut args, &arg));
+                    grid_mutations = true;
+                    grid_changed = true;
+                }
+                "--step-sizes" => {
+                    grid.step_sizes = try!(parse_list(&mut args, &arg));
+                    grid_step_sizes = true;
+                    grid_changed = true;
+                }
+                "--mutation-indices" => {
+                    grid.mutation_indices = try!(parse_list(&mut args, &arg));
+                    grid_changed = true;
+                }
+                "--seeds" => {
+                    grid.seeds = try!(parse_list(&mut args, &arg));
+                    grid_changed = true;
+                }
+                "--runs" => {
+                    let runs: usize = try!(parse_value(&mut args, &arg));
+                    if runs == 0 {
+                        return Err("--runs must be positive".to_string());
+                    }
+                    grid.seeds = (0..runs).collect();
+                    grid_changed = true;
+                }
+                "--target-fitness" => {
+                    grid.target_fitness = Some(try!(parse_value(&mut args, &arg)));
+                    grid_changed = true;
+                }
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+
+        match (alternatives, min_distance) {
+            (Some(count), distance) => {
+                options.alternatives = Some((count, distance.unwrap_or(5.0)));
+            }
+            (None, Some(_)) => return Err("--min-distance requires --alternatives".to_string()),
+            (None, None) => {}
+        }
+        match (local_search, refine_every) {
+            (Some(_), Some(_)) if options.optimizer != OptimizerKind::Genetic => {
+                return Err("--refine-every requires --optimizer ga, the other optimizers only \
+                            refine the final result"
+                               .to_string())
+            }
+            (Some(method), every) => options.local_search = Some((method, every.unwrap_or(100))),
+            (None, Some(_)) => return Err("--refine-every requires --local-search".to_string()),
+            (None, None) => {}
+        }
+        if code_preview {
+            options.preview = PreviewMode::Code(language);
+        }
+        if let Some(format) = log_format {
+            match options.log {
+                Some((_, ref mut log)) => *log = format,
+                None => return Err("--log-format requires --log".to_string()),
+            }
+        }
+        if let Some(format) = export_format {
+            match options.export {
+                Some((_, ref mut export)) => *export = format,
+                None => return Err("--export-format requires --export".to_string()),
+            }
+        }
+        if benchmark {
+            if !grid_optimizers {
+                grid.optimizers = vec![options.optimizer];
+            }
+            if !grid_mutations {
+                grid.mutations = vec![options.mutation];
+            }
+            if !grid_step_sizes {
+                grid.step_sizes = vec![options.step_size];
+            }
+            if !grid_fitness_caches {
+                grid.fitness_caches = vec![options.fitness_cache];
+            }
+            grid.heat = options.heat;
+            options.benchmark = Some(grid);
+        } else if grid_changed {
+            return Err("benchmark settings require --benchmark".to_string());
+        }
+        Ok(options)
+    }
+}
+
+impl Options {
+    /// Named roles are kept in place unless another crossover is requested.
+    pub fn crossover(&self) -> CrossoverOperator {
+        let mut crossover = if self.roles.is_empty() {
+            CrossoverOperator::default()
+        } else {
+            CrossoverOperator::role_preserving()
+        };
+        if let Some(matching) = self.matching {
+            crossover.matching = matching;
+        }
+        if let Some(blend) = self.blend {
+            crossover.blend = blend;
+        }
+        crossover
+    }
+}
+
+fn parse_value<I, T>(args: &mut I, flag: &str) -> Result<T, String>
+    where I: Iterator<Item = String>,
+          T: FromStr
+{
+    let string = try!(value(args, flag));
+    string.parse().map_err(|_| format!("invalid value {} for {}", string, flag))
+}
+
+fn parse_list<I, T>(args: &mut I, flag: &str) -> Result<Vec<T>, String>
+    where I: Iterator<Item = String>,
+          T: FromStr
+{
+    let string = try!(value(args, flag));
+    string.split(',')
+          .map(|item| {
+              item.trim()
+                  .parse()
+                  .map_err(|_| format!("invalid value {} for {}", item, flag))
+          })
+          .collect()
+}
+
+/// A list of positive counts, like population sizes.
+fn parse_counts<I>(args: &mut I, flag: &str) -> Result<Vec<usize>, String>
+    where I: Iterator<Item = String>
+{
+    let counts: Vec<usize> = try!(parse_list(args, flag));
+    if counts.contains(&0) {
+        return Err(format!("{} must be positive", flag));
+    }
+    Ok(counts)
+}
+
+fn value<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> Result<String, String> {
+    args.next().ok_or(format!("{} expects a value", flag))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn benchmark_counts() {
+        let grid = parse(&["--benchmark", "--runs", "3", "--populations", "20, 50"])
+                       .unwrap()
+                       .benchmark
+                       .unwrap();
+        assert_eq!(grid.seeds, vec![0, 1, 2]);
+        assert_eq!(grid.population_sizes, vec![20, 50]);
+        assert_eq!(grid.generations, vec![500]);
+    }
+
+    #[test]
+    fn counts_must_be_positive() {
+        assert!(parse(&["--benchmark", "--runs", "0"]).is_err());
+        assert!(parse(&["--benchmark", "--populations", "0"]).is_err());
+        assert!(parse(&["--benchmark", "--generations", "100,0"]).is_err());
+        assert!(parse(&["--benchmark", "--populations", "-1"]).is_err());
+        assert!(parse(&["--runs", "3"]).is_err());
+    }
+
+    #[test]
+    fn refine_every_needs_the_genetic_algorithm() {
+        assert!(parse(&["--local-search", "coordinate", "--refine-every", "10"]).is_ok());
+        assert!(parse(&["--optimizer", "cmaes", "--local-search", "coordinate"]).is_ok());
+        assert!(parse(&["--optimizer", "cmaes", "--local-search", "coordinate", "--refine-every",
+                        "10"])
+                    .is_err());
+    }
+}
@@ -0,0 +1,628 @@
+use fitness::{Target, Stat, Parameter, Expression, Operator, Function};
+use fitness::{Strength, Curve};
+use error::Error;
+use fitness::Parameter::*;
+use fitness::Stat::*;
+use fitness::TargetDirection::*;
+
+/// Parses a target like `approximate 40 min freedist 1 2` or
+/// `maximize min fixeddist 1 0.5 signed`. A leading `require` makes a
+/// threshold target a hard constraint and `priority 2` lowers its priority.
+/// Instead of a statistic and parameter, the target can be an expression
+/// like `min(fixeddist) - 0.5 * stddev(luminance)`, optionally followed by
+/// `;` and the strength.
+pub fn line_to_target(line: &str) -> Result<Target, Error> {
+    parse_target(line).map_err(|err| Error::parse("target", err))
+}
+
+fn parse_target(line: &str) -> Result<Target, String> {
+    let text = line.trim();
+    let mut line = text.split(" ").peekable();
+    let mut required = false;
+    let mut priority = 1;
+    loop {
+        match line.peek().cloned() {
+            Some("require") => required = true,
+            Some("priority") => {
+                line.next();
+                priority = try!(line.peek()
+                                    .and_then(|s| s.parse().ok())
+                                    .and_then(|p| if p > 0 { Some(p) } else { None })
+                                    .ok_or("expected a positive priority"));
+            }
+            _ => break,
+        }
+        line.next();
+    }
+    let direction = try!(line.next().ok_or("expected string").and_then(|string| {
+        match string {
+            "minimize" => Ok(Minimize),
+            "maximize" => Ok(Maximize),
+            "approximate" => {
+                let value = try!(next_float(&mut line));
+                if line.peek() == Some(&"within") {
+                    line.next();
+                    next_float(&mut line).map(|tolerance| Approximate(value, tolerance.abs()))
+                } else {
+                    Ok(Approximate(value, 0.0))
+                }
+            }
+            "atleast" => next_float(&mut line).map(AtLeast),
+            "atmost" => next_float(&mut line).map(AtMost),
+            "between" => {
+                let lower = try!(next_float(&mut line));
+                let upper = try!(next_float(&mut line));
+                if lower <= upper {
+                    Ok(Between(lower, upper))
+                } else {
+                    Err("expected the lower bound first")
+                }
+            }
+            _ => Err("expected minimize, maximize, approximate, atleast, atmost or between"),
+        }
+    }));
+
+    let rest = line.collect::<Vec<&str>>().join(" ");
+    let (expression, strength) = if rest.contains('(') {
+        // the rest is the end of the line, as it was split by single spaces
+        let offset = text.len() - rest.len();
+        let mut parts = rest.splitn(2, ';');
+        let expression = try!(parse_expression_at(parts.next().unwrap_or(""), offset));
+        (expression, parts.next().unwrap_or("").to_string())
+    } else {
+        let mut tokens = rest.split(" ");
+        let stat = try!(parse_stat(&mut tokens));
+        let parameter = try!(tokens.next().ok_or("expected string").and_then(parse_parameter));
+        (Expression::Stat(stat, parameter), tokens.collect::<Vec<&str>>().join(" "))
+    };
+
+    // the factor and exponent, optionally followed or preceded by a curve
+    let mut numbers = vec![];
+    let mut curve = None;
+    for token in strength.split(" ").filter(|token| !token.is_empty()) {
+        match token.parse::<f32>() {
+            Ok(_) if numbers.len() == 2 => {
+                return Err(format!("unexpected {}, expected at most a factor and an exponent",
+                                   token))
+            }
+            Ok(number) => numbers.push(number),
+            Err(_) if curve.is_some() => {
+                return Err(format!("unexpected {}, expected at most one curve", token))
+            }
+            Err(_) => curve = Some(try!(token.parse())),
+        }
+    }
+
+    if required {
+        match direction {
+            Minimize | Maximize => {
+                return Err("require expects atleast, atmost, between or approximate".to_string())
+            }
+            _ => {}
+        }
+    }
+
+    let mut target = Target::from_expression(direction,
+                                             expression,
+                                             Strength {
+                                                 factor: numbers.get(0).cloned().unwrap_or(1.0),
+                                                 exponent: numbers.get(1).cloned().unwrap_or(1.0),
+                                                 curve: curve.unwrap_or(Curve::default()),
+                                             });
+    target.required = required;
+    target.priority = priority;
+    Ok(target)
+}
+
+/// Parses `mean`, `stddev`, `min`, `max`, `median`, `range`, `sum`,
+/// `10th percentile` (or `percentile 10`), `count below 15`, `softmin 2` or
+/// `softmax 2`.
+fn parse_stat<'a, I: Iterator<Item = &'a str>>(line: &mut I) -> Result<Stat, &'static str> {
+    let string = try!(line.next().ok_or("expected string"));
+    match string {
+        "mean" => Ok(Mean),
+        "stddev" => Ok(StdDev),
+        "min" => Ok(Min),
+        "max" => Ok(Max),
+        "median" => Ok(Median),
+        "range" => Ok(Range),
+        "sum" => Ok(Sum),
+        "percentile" => next_float(line).and_then(percentile),
+        "count" => {
+            match line.next() {
+                Some("below") => next_float(line).map(CountBelow),
+                _ => Err("expected below"),
+            }
+        }
+        "softmin" => next_float(line).and_then(temperature).map(SoftMin),
+        "softmax" => next_float(line).and_then(temperature).map(SoftMax),
+        ordinal => {
+            let number = ordinal.trim_right_matches(|c: char| c.is_alphabetic());
+            match (number.parse(), line.next()) {
+                (Ok(p), Some("percentile")) if number != ordinal => percentile(p),
+                _ => {
+                    Err("expected mean, stddev, min, max, median, range, sum, Nth percentile, \
+                         count below, softmin or softmax")
+                }
+            }
+        }
+    }
+}
+
+fn parse_parameter(name: &str) -> Result<Parameter, &'static str> {
+    [Chroma, Luminance, FreeDistance, FixedDistance]
+        .iter()
+        .find(|p| p.keyword() == name)
+        .cloned()
+        .ok_or("expected chroma, luminance, freedist or fixeddist")
+}
+
+fn next_float<'a, I: Iterator<Item = &'a str>>(line: &mut I) -> Result<f32, &'static str> {
+    line.next()
+        .ok_or("expected string")
+        .and_then(|s| s.parse().map_err(|_| "expected float"))
+        .and_then(|f: f32| if f.is_nan() { Err("expected float") } else { Ok(f) })
+}
+
+fn percentile(p: f32) -> Result<Stat, &'static str> {
+    if p >= 0.0 && p <= 100.0 {
+        Ok(Percentile(p))
+    } else {
+        Err("percentile must be between 0 and 100")
+    }
+}
+
+fn temperature(t: f32) -> Result<f32, &'static str> {
+    if t > 0.0 {
+        Ok(t)
+    } else {
+        Err("temperature must be positive")
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f32),
+    Name(String),
+    Symbol(char),
+}
+
+/// The tokens with the column they start at, counted from 1 plus `offset`.
+fn tokenize(text: &str, offset: usize) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let token = if c.is_digit(10) || c == '.' {
+            let mut end = i + c.len_utf8();
+            while let Some(&(j, d)) = chars.peek() {
+                if !(d.is_digit(10) || d == '.') {
+                    break;
+                }
+                end = j + d.len_utf8();
+                chars.next();
+            }
+            let number = &text[i..end];
+            Token::Number(try!(number.parse()
+                                     .map_err(|_| {
+                                         format!("column {}: invalid number {}",
+                                                 offset + i + 1,
+                                                 number)
+                                     })))
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = i + c.len_utf8();
+            while let Some(&(j, d)) = chars.peek() {
+                if !(d.is_alphanumeric() || d == '_') {
+                    break;
+                }
+                end = j + d.len_utf8();
+                chars.next();
+            }
+            Token::Name(text[i..end].to_string())
+        } else if "+-*/^(),".contains(c) {
+            Token::Symbol(c)
+        } else {
+            return Err(format!("column {}: unexpected character {}", offset + i + 1, c));
+        };
+        tokens.push((offset + i + 1, token));
+    }
+    Ok(tokens)
+}
+
+/// A function argument: parameters are only valid as the first argument of
+/// a statistic.
+enum Argument {
+    Parameter(usize, Parameter),
+    Value(Expression),
+}
+
+/// Recursive descent parser for expressions. Errors name the column.
+struct ExpressionParser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    /// Column after the last token, for errors at the end.
+    end: usize,
+}
+
+impl ExpressionParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|&(_, ref token)| token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens.get(self.position).map(|&(column, _)| column).unwrap_or(self.end)
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        let found = match self.peek() {
+            Some(&Token::Number(x)) => format!("{}", x),
+            Some(&Token::Name(ref name)) => name.clone(),
+            Some(&Token::Symbol(c)) => format!("'{}'", c),
+            None => "the end".to_string(),
+        };
+        Err(format!("column {}: {}, found {}", self.column(), message, found))
+    }
+
+    fn accept(&mut self, symbol: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(symbol)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, symbol: char) -> Result<(), String> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            self.error(&format!("expected '{}'", symbol))
+        }
+    }
+
+    /// Sum := Product (('+' | '-') Product)*
+    fn sum(&mut self) -> Result<Expression, String> {
+        let mut expression = try!(self.product());
+        loop {
+            let operator = if self.accept('+') {
+                Operator::Add
+            } else if self.accept('-') {
+                Operator::Subtract
+            } else {
+                return Ok(expression);
+            };
+            let right = try!(self.product());
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(right));
+        }
+    }
+
+    /// Product := Unary (('*' | '/') Unary)*
+    fn product(&mut self) -> Result<Expression, String> {
+        let mut expression = try!(self.unary());
+        loop {
+            let operator = if self.accept('*') {
+                Operator::Multiply
+            } else if self.accept('/') {
+                Operator::Divide
+            } else {
+                return Ok(expression);
+            };
+            let right = try!(self.unary());
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(right));
+        }
+    }
+
+    /// Unary := '-' Unary | Primary ('^' Unary)?
+    fn unary(&mut self) -> Result<Expression, String> {
+        if self.accept('-') {
+            return Ok(Expression::Negate(Box::new(try!(self.unary()))));
+        }
+        let base = try!(self.primary());
+        if self.accept('^') {
+            let exponent = try!(self.unary());
+            return Ok(Expression::Binary(Operator::Power, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    /// Primary := Number | '(' Sum ')' | Name '(' Argument (',' Argument)* ')'
+    fn primary(&mut self) -> Result<Expression, String> {
+        let column = self.column();
+        let token = self.peek().cloned();
+        match token {
+            Some(Token::Number(x)) => {
+                self.position += 1;
+                Ok(Expression::Number(x))
+            }
+            Some(Token::Symbol('(')) => {
+                self.position += 1;
+                let expression = try!(self.sum());
+                try!(self.expect(')'));
+                Ok(expression)
+            }
+            Some(Token::Name(name)) => {
+                self.position += 1;
+                if !self.accept('(') {
+                    return match parse_parameter(&name) {
+                        Ok(_) => {
+                            Err(format!("column {}: {} is a parameter, apply a statistic like \
+                                         mean({})",
+                                        column,
+                                        name,
+                                        name))
+                        }
+                        Err(_) => Err(format!("column {}: unknown name {}", column, name)),
+                    };
+                }
+                let mut arguments = vec![try!(self.argument())];
+                while self.accept(',') {
+                    arguments.push(try!(self.argument()));
+                }
+                try!(self.expect(')'));
+                call(&name, column, arguments)
+            }
+            _ => self.error("expected a number, a function or '('"),
+        }
+    }
+
+    fn argument(&mut self) -> Result<Argument, String> {
+        let column = self.column();
+        let parameter = match (self.tokens.get(self.position), self.tokens.get(self.position + 1)) {
+            (Some(&(_, Token::Name(ref name))), Some(&(_, Token::Symbol(',')))) |
+            (Some(&(_, Token::Name(ref name))), Some(&(_, Token::Symbol(')')))) |
+            (Some(&(_, Token::Name(ref name))), None) => parse_parameter(name).ok(),
+            _ => None,
+        };
+        match parameter {
+            Some(parameter) => {
+                self.position += 1;
+                Ok(Argument::Parameter(column, parameter))
+            }
+            None => self.sum().map(Argument::Value),
+        }
+    }
+}
+
+/// Checks the arguments of a function call and builds the expression.
+fn call(name: &str, column: usize, arguments: Vec<Argument>) -> Result<Expression, String> {
+    let error = |message: String| Err(format!("column {}: {}", column, message));
+    let stat_functions = ["mean", "stddev", "min", "max", "median", "range", "sum",
+                          "percentile", "countbelow", "softmin", "softmax"];
+    let mut arguments = arguments.into_iter();
+    let first = arguments.next();
+    let rest: Vec<Argument> = arguments.collect();
+
+    if let Some(Argument::Parameter(_, parameter)) = first {
+        if !stat_functions.contains(&name) {
+            return error(format!("{} expects numbers, not the parameter {}",
+                                 name,
+                                 parameter.keyword()));
+        }
+        // the parameter of a statistic like `percentile` has to be a constant
+        let constant = match rest.first() {
+            Some(&Argument::Value(Expression::Number(x))) => Some(x),
+            Some(&Argument::Value(Expression::Negate(ref e))) => {
+                match **e {
+                    Expression::Number(x) => Some(-x),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        let stat = match (name, rest.len(), constant) {
+            ("mean", 0, _) => Ok(Mean),
+            ("stddev", 0, _) => Ok(StdDev),
+            ("min", 0, _) => Ok(Min),
+            ("max", 0, _) => Ok(Max),
+            ("median", 0, _) => Ok(Median),
+            ("range", 0, _) => Ok(Range),
+            ("sum", 0, _) => Ok(Sum),
+            ("percentile", 1, Some(p)) => percentile(p),
+            ("countbelow", 1, Some(threshold)) => Ok(CountBelow(threshold)),
+            ("softmin", 1, Some(t)) => temperature(t).map(SoftMin),
+            ("softmax", 1, Some(t)) => temperature(t).map(SoftMax),
+            ("percentile", _, _) | ("countbelow", _, _) | ("softmin", _, _) |
+            ("softmax", _, _) => {
+                return error(format!("{} expects a parameter and a number, like {}({}, 10)",
+                                     name,
+                                     name,
+                                     parameter.keyword()))
+            }
+            _ => return error(format!("{} expects only a parameter", name)),
+        };
+        return match stat {
+            Ok(stat) => Ok(Expression::Stat(stat, parameter)),
+            Err(message) => error(message.to_string()),
+        };
+    }
+
+    let mut values = vec![];
+    for argument in first.into_iter().chain(rest.into_iter()) {
+        match argument {
+            Argument::Value(e) => values.push(e),
+            Argument::Parameter(column, parameter) => {
+                return Err(format!("column {}: {} expects numbers, not the parameter {}",
+                                   column,
+                                   name,
+                                   parameter.keyword()))
+            }
+        }
+    }
+    let (function, arity) = match name {
+        "abs" => (Function::Abs, Some(1)),
+        "sqrt" => (Function::Sqrt, Some(1)),
+        "ln" => (Function::Ln, Some(1)),
+        "exp" => (Function::Exp, Some(1)),
+        "min" => (Function::Min, None),
+        "max" => (Function::Max, None),
+        _ if stat_functions.contains(&name) => {
+            return error(format!("{} expects a parameter (chroma, luminance, freedist or \
+                                  fixeddist)",
+                                 name))
+        }
+        _ => return error(format!("unknown function {}", name)),
+    };
+    match arity {
+        Some(n) if values.len() != n => {
+            error(format!("{} expects {} argument, got {}", name, n, values.len()))
+        }
+        None if values.len() < 2 => {
+            error(format!("{} of numbers expects at least 2 arguments, or a parameter", name))
+        }
+        _ => Ok(Expression::Function(function, values)),
+    }
+}
+
+/// Parses an expression like `abs(mean(luminance) - 60)` of numbers, `+`, `-`,
+/// `*`, `/`, `^`, the functions `abs`, `sqrt`, `ln`, `exp`, `min` and `max` of
+/// numbers and the statistics of parameters: `mean(freedist)`, `stddev`,
+/// `min`, `max`, `median`, `range`, `sum`, `percentile(freedist, 10)`,
+/// `countbelow(freedist, 15)`, `softmin(freedist, 2)` and `softmax`.
+pub fn parse_expression(text: &str) -> Result<Expression, Error> {
+    parse_expression_at(text, 0).map_err(|err| Error::parse("expression", err))
+}
+
+/// Parses an expression which starts after `offset` bytes of a line, so that
+/// errors name the column in the line.
+fn parse_expression_at(text: &str, offset: usize) -> Result<Expression, String> {
+    let mut parser = ExpressionParser {
+        tokens: try!(tokenize(text, offset)),
+        position: 0,
+        end: offset + text.len() + 1,
+    };
+    let expression = try!(parser.sum());
+    if parser.position < parser.tokens.len() {
+        return parser.error("expected an operator");
+    }
+    Ok(expression)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use error::Error;
+    use fitness::{Expression, Operator};
+    use fitness::Parameter::*;
+    use fitness::Stat::*;
+    use fitness::TargetDirection::*;
+
+    #[test]
+    fn parse_stats() {
+        let stat = |line: &str| line_to_target(line).map(|t| t.expression.as_stat().unwrap().0);
+        assert_eq!(stat("maximize 10th percentile freedist").ok(), Some(Percentile(10.0)));
+        assert_eq!(stat("maximize percentile 90 freedist").ok(), Some(Percentile(90.0)));
+        assert_eq!(stat("minimize count below 15 freedist").ok(), Some(CountBelow(15.0)));
+        assert_eq!(stat("maximize softmin 2 fixeddist 1 2").ok(), Some(SoftMin(2.0)));
+        assert_eq!(stat("minimize range luminance").ok(), Some(Range));
+        assert!(stat("maximize 120th percentile freedist").is_err());
+        assert!(stat("maximize softmax 0 freedist").is_err());
+    }
+
+    #[test]
+    fn parse_directions() {
+        let direction = |line: &str| line_to_target(line).map(|t| t.direction);
+        match direction("atleast 4.5 min fixeddist") {
+            Ok(AtLeast(threshold)) => assert_eq!(threshold, 4.5),
+            _ => panic!("expected at least"),
+        }
+        match direction("between 20 40 mean chroma") {
+            Ok(Between(lower, upper)) => assert_eq!((lower, upper), (20.0, 40.0)),
+            _ => panic!("expected between"),
+        }
+        match direction("approximate 40 within 5 min freedist") {
+            Ok(Approximate(value, tolerance)) => assert_eq!((value, tolerance), (40.0, 5.0)),
+            _ => panic!("expected approximate"),
+        }
+        match direction("approximate 40 min freedist") {
+            Ok(Approximate(_, tolerance)) => assert_eq!(tolerance, 0.0),
+            _ => panic!("expected approximate"),
+        }
+        assert!(direction("between 40 20 mean chroma").is_err());
+    }
+
+    #[test]
+    fn parse_strength() {
+        let target = line_to_target("maximize min fixeddist 2 0.5 signed").unwrap();
+        assert_eq!((target.strength.factor, target.strength.exponent), (2.0, 0.5));
+        assert_eq!(target.strength.curve, Curve::SignedPower);
+        let target = line_to_target("minimize stddev luminance 1 2").unwrap();
+        assert_eq!(target.strength.curve, Curve::Power);
+        assert!(line_to_target("minimize stddev luminance 1 2 cubic").is_err());
+        assert!(line_to_target("minimize stddev luminance 1 2 3").is_err());
+        assert!(line_to_target("minimize stddev luminance 1 signed 2 3").is_err());
+        assert_eq!(line_to_target("minimize stddev luminance 2 huber:5 power")
+                       .unwrap_err()
+                       .to_string(),
+                   "target: unexpected power, expected at most one curve");
+    }
+
+    #[test]
+    fn parse_require() {
+        assert!(line_to_target("require atleast 30 min fixeddist").unwrap().required);
+        assert!(!line_to_target("atleast 30 min fixeddist").unwrap().required);
+        assert!(line_to_target("require maximize min fixeddist").is_err());
+    }
+
+    #[test]
+    fn parse_priority() {
+        let target = line_to_target("priority 2 require atleast 30 min fixeddist").unwrap();
+        assert_eq!((target.priority, target.required), (2, true));
+        assert_eq!(line_to_target("maximize mean chroma").unwrap().priority, 1);
+        assert!(line_to_target("priority 0 maximize mean chroma").is_err());
+    }
+
+    #[test]
+    fn parse_expressions() {
+        let expression = parse_expression("min(fixeddist) - 0.5 * stddev(luminance)").unwrap();
+        let spread = Expression::Binary(Operator::Multiply,
+                                        Box::new(Expression::Number(0.5)),
+                                        Box::new(Expression::Stat(StdDev, Luminance)));
+        assert_eq!(expression,
+                   Expression::Binary(Operator::Subtract,
+                                      Box::new(Expression::Stat(Min, FixedDistance)),
+                                      Box::new(spread)));
+        let text = "abs(mean(luminance) - 60) / -(2 - percentile(freedist, 10)) ^ 2";
+        assert_eq!(parse_expression(text).unwrap().to_string(), text);
+        assert_eq!(parse_expression("max(1, 2, -3)").unwrap().to_string(), "max(1, 2, -3)");
+    }
+
+    #[test]
+    fn expression_errors() {
+        let error = |text: &str| {
+            match parse_expression(text) {
+                Err(Error::Parse { message, .. }) => message,
+                _ => panic!("expected a parse error for {}", text),
+            }
+        };
+        assert_eq!(error("mean(freedist) +"),
+                   "column 17: expected a number, a function or '(', found the end");
+        assert_eq!(error("2 * freedist"),
+                   "column 5: freedist is a parameter, apply a statistic like mean(freedist)");
+        assert_eq!(error("abs(freedist)"),
+                   "column 1: abs expects numbers, not the parameter freedist");
+        assert_eq!(error("mean(2)"),
+                   "column 1: mean expects a parameter (chroma, luminance, freedist or fixeddist)");
+        assert_eq!(error("percentile(freedist)"),
+                   "column 1: percentile expects a parameter and a number, like \
+                    percentile(freedist, 10)");
+        assert_eq!(error("foo(1)"), "column 1: unknown function foo");
+        assert_eq!(error("mean(chroma) mean(chroma)"),
+                   "column 14: expected an operator, found mean");
+    }
+
+    #[test]
+    fn expression_targets() {
+        let target = line_to_target("minimize abs(mean(luminance) - 60); 2 1").unwrap();
+        assert_eq!(target.name(), "abs(mean(luminance) - 60)");
+        assert_eq!(target.strength.factor, 2.0);
+        assert!(line_to_target("maximize min(fixeddist) - 0.5 * stddev(luminance)").is_ok());
+        assert!(line_to_target("maximize min(fixeddist) -").is_err());
+        assert_eq!(line_to_target("maximize 2 * mean(freedist) + freedist")
+                       .unwrap_err()
+                       .to_string(),
+                   "target: column 31: freedist is a parameter, apply a statistic like \
+                    mean(freedist)");
+    }
+}
@@ -0,0 +1,274 @@
+use palette::Lab;
+use fitness::ColorSchemeProblemDescription;
+use colorscheme::ColorScheme;
+use color::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Language {
+    Rust,
+    Python,
+    Shell,
+}
+
+impl Language {
+    pub fn from_name(name: &str) -> Result<Language, String> {
+        match name {
+            "rust" => Ok(Language::Rust),
+            "python" => Ok(Language::Python),
+            "shell" => Ok(Language::Shell),
+            _ => Err(format!("unknown language {}, expected rust, python or shell", name)),
+        }
+    }
+
+    fn sample(&self) -> &'static str {
+        match *self {
+            Language::Rust => include_str!("../samples/sample.rs"),
+            Language::Python => include_str!("../samples/sample.py"),
+            Language::Shell => include_str!("../samples/sample.sh"),
+        }
+    }
+
+    fn keywords(&self) -> &'static [&'static str] {
+        match *self {
+            Language::Rust => {
+                &["as", "break", "const", "continue", "crate", "else", "enum", "extern", "false",
+                  "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+                  "pub", "ref", "return", "self", "static", "struct", "super", "trait", "true",
+                  "type", "unsafe", "use", "where", "while"]
+            }
+            Language::Python => {
+                &["and", "as", "class", "def", "elif", "else", "False", "for", "from", "if",
+                  "import", "in", "is", "lambda", "None", "not", "or", "pass", "return", "self",
+                  "True", "while", "with", "yield"]
+            }
+            Language::Shell => {
+                &["case", "do", "done", "elif", "else", "esac", "exit", "fi", "for", "function",
+                  "if", "in", "then", "until", "while"]
+            }
+        }
+    }
+
+    fn comment(&self) -> &'static str {
+        match *self {
+            Language::Rust => "//",
+            Language::Python | Language::Shell => "#",
+        }
+    }
+
+    fn is_type(&self, word: &str) -> bool {
+        let primitive = match *self {
+            Language::Rust => {
+                ["bool", "char", "f32", "f64", "i32", "i64", "str", "u8", "u32", "u64", "usize"]
+                    .contains(&word)
+            }
+            Language::Python => ["dict", "float", "int", "list", "object", "str"].contains(&word),
+            Language::Shell => false,
+        };
+        primitive ||
+        (*self != Language::Shell && word.chars().next().map_or(false, |c| c.is_uppercase()))
+    }
+
+    fn is_command(&self, word: &str) -> bool {
+        *self == Language::Shell &&
+        ["cd", "du", "echo", "find", "grep", "head", "sort", "test"].contains(&word)
+    }
+}
+
+/// Syntactic roles of the sample code. Every role except `Plain` is drawn in
+/// one of the free colors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TokenClass {
+    Keyword,
+    String,
+    Comment,
+    Function,
+    Type,
+    Number,
+    Plain,
+}
+
+impl TokenClass {
+    /// The role name which picks this class's color if the roles are named.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            TokenClass::Keyword => "keyword",
+            TokenClass::String => "string",
+            TokenClass::Comment => "comment",
+            TokenClass::Function => "function",
+            TokenClass::Type => "type",
+            TokenClass::Number => "number",
+            TokenClass::Plain => "plain",
+        }
+    }
+
+    fn role(&self) -> Option<usize> {
+        match *self {
+            TokenClass::Keyword => Some(0),
+            TokenClass::String => Some(1),
+            TokenClass::Comment => Some(2),
+            TokenClass::Function => Some(3),
+            TokenClass::Type => Some(4),
+            TokenClass::Number => Some(5),
+            TokenClass::Plain => None,
+        }
+    }
+}
+
+pub fn tokenize(line: &str, language: Language) -> Vec<(TokenClass, String)> {
+    let chars: Vec<char> = line.chars().collect();
+    let comment: Vec<char> = language.comment().chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        let class = if chars[i..].starts_with(&comment) &&
+                       !(language == Language::Shell && i > 0 && chars[i - 1] == '$') {
+            i = chars.len();
+            TokenClass::Comment
+        } else if c == '"' || (c == '\'' && language != Language::Rust) {
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(chars.len());
+            TokenClass::String
+        } else if c.is_digit(10) {
+            while i < chars.len() &&
+                  (chars[i].is_alphanumeric() || chars[i] == '.' || chars[i] == '_') {
+                i += 1;
+            }
+            TokenClass::Number
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().cloned().collect();
+            let next = chars.get(i).cloned();
+            if language.keywords().contains(&word.as_ref()) {
+                TokenClass::Keyword
+            } else if language.is_type(&word) {
+                TokenClass::Type
+            } else if next == Some('(') || (language == Language::Rust && next == Some('!')) ||
+                      language.is_command(&word) {
+                TokenClass::Function
+            } else {
+                TokenClass::Plain
+            }
+        } else {
+            i += 1;
+            TokenClass::Plain
+        };
+        let text: String = chars[start..i].iter().cloned().collect();
+
+        // merge consecutive tokens of the same class to keep the escape sequences short
+        let merge = match tokens.last() {
+            Some(&(last, _)) => last == class,
+            None => false,
+        };
+        if merge {
+            tokens.last_mut().unwrap().1.push_str(&text);
+        } else {
+            tokens.push((class, text));
+        }
+    }
+    tokens
+}
+
+/// With named roles, a token class is drawn in the free color of the same
+/// name. Otherwise the classes are assigned to the free colors in order.
+fn class_color(class: TokenClass,
+               descr: &ColorSchemeProblemDescription,
+               free_colors: &[Lab])
+               -> Option<Lab> {
+    if free_colors.is_empty() {
+        None
+    } else if descr.roles.is_empty() {
+        class.role().map(|role| free_colors[role % free_colors.len()])
+    } else {
+        descr.roles.iter().position(|role| role == class.name()).map(|i| free_colors[i])
+    }
+}
+
+/// Renders the bundled code sample on every fixed color with the free colors
+/// assigned to the token classes. Plain text uses the fixed color that is
+/// most distant to the background.
+pub fn print_code_preview(scheme: &ColorScheme,
+                          descr: &ColorSchemeProblemDescription,
+                          language: Language) {
+    let free_colors = scheme.display_colors(descr);
+    let lines: Vec<&str> = language.sample().lines().collect();
+    let width = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) + 2;
+
+    for (bg_index, bg) in descr.fixed_colors.iter().enumerate() {
+        let plain = plain_color(bg_index, descr);
+        for line in lines.iter() {
+            print_colored_text(bg, &plain, " ");
+            for (class, text) in tokenize(line, language) {
+                let fg = class_color(class, descr, &free_colors).unwrap_or(plain);
+                print_colored_text(bg, &fg, &text);
+            }
+            let padding: String = (line.chars().count() + 1..width).map(|_| ' ').collect();
+            print_colored_text(bg, &plain, &padding);
+            println!("");
+        }
+        println!("");
+    }
+}
+
+fn plain_color(bg_index: usize, descr: &ColorSchemeProblemDescription) -> Lab {
+    let bg = &descr.fixed_colors[bg_index];
+    descr.fixed_colors
+         .iter()
+         .enumerate()
+         .filter(|&(i, _)| i != bg_index)
+         .map(|(_, col)| (distance(bg, col), *col))
+         .fold(None, |best: Option<(f32, Lab)>, (dist, col)| {
+             match best {
+                 Some((best_dist, _)) if best_dist >= dist => best,
+                 _ => Some((dist, col)),
+             }
+         })
+         .map(|(_, col)| col)
+         .unwrap_or_else(|| if bg.l > 0.5 {
+             Lab::new(0.0, 0.0, 0.0)
+         } else {
+             Lab::new(1.0, 0.0, 0.0)
+         })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::TokenClass::{Comment, Function, Keyword, Number, Plain, Type};
+
+    fn tokens(expected: &[(TokenClass, &str)]) -> Vec<(TokenClass, String)> {
+        expected.iter().map(|&(class, text)| (class, text.to_string())).collect()
+    }
+
+    #[test]
+    fn tokenize_rust() {
+        assert_eq!(tokenize("let x: u32 = f(\"a\"); // c", Language::Rust),
+                   tokens(&[(Keyword, "let"),
+                            (Plain, " x: "),
+                            (Type, "u32"),
+                            (Plain, " = "),
+                            (Function, "f"),
+                            (Plain, "("),
+                            (TokenClass::String, "\"a\""),
+                            (Plain, "); "),
+                            (Comment, "// c")]));
+    }
+
+    #[test]
+    fn tokenize_shell() {
+        assert_eq!(tokenize("echo \"$#\" 42 # done", Language::Shell),
+                   tokens(&[(Function, "echo"),
+                            (Plain, " "),
+                            (TokenClass::String, "\"$#\""),
+                            (Plain, " "),
+                            (Number, "42"),
+                            (Plain, " "),
+                            (Comment, "# done")]));
+    }
+}
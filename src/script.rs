@@ -0,0 +1,250 @@
+//! Custom fitness functions as scripts, for criteria no statistic of a
+//! parameter can express. The language is a subset of Rhai, see the
+//! `interpreter` module.
+//!
+//! A script sees the colors of a scheme as the arrays `free` and `fixed`
+//! (with `free_count` and `fixed_count` elements), whose elements have the
+//! float properties `l`, `a`, `b` (CIE Lab, `l` 0–100), `lightness`,
+//! `chroma`, `hue` (LCh, hue in degrees) and `red`, `green`, `blue` (sRGB,
+//! 0–1). The value of the script is its score, which is added to the fitness.
+//! Named sub-scores recorded with `scores.add("contrast", x)` are added as
+//! well and shown separately in the fitness printout:
+//!
+//! ```text
+//! for color in free {
+//!     scores.add("light", color.lightness / 10.0);
+//! }
+//! 0.0
+//! ```
+//!
+//! A script can't print and has no access to files, the network or the
+//! process. It is parsed once per change of the file. It fails like after any
+//! other error if it runs more than `MAX_OPERATIONS` operations per scheme,
+//! builds strings or arrays beyond `MAX_STRING_SIZE` bytes or
+//! `MAX_ARRAY_SIZE` items, or records more than `MAX_SCORES` named scores, so
+//! neither an endless loop nor a growing value can hang the optimizer.
+
+use palette::{Lab, Lch, Rgb};
+use palette::pixel::Srgb;
+use palette::Limited;
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io;
+use std::io::Read;
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use cache::key;
+use error::Error;
+use interpreter::{Program, Value};
+pub use interpreter::{MAX_ARRAY_SIZE, MAX_OPERATIONS, MAX_SCORES, MAX_STRING_SIZE};
+
+/// A color as seen by a script.
+fn color_value(color: &Lab) -> Value {
+    let lch: Lch = (*color).into();
+    let mut rgb: Rgb = (*color).into();
+    rgb.clamp_self();
+    let srgb = Srgb::from_linear(rgb);
+    let properties = vec![("l", color.l as f64 * 100.0),
+                          ("a", color.a as f64 * 128.0),
+                          ("b", color.b as f64 * 128.0),
+                          ("lightness", lch.l as f64 * 100.0),
+                          ("chroma", lch.chroma as f64 * 128.0),
+                          ("hue", lch.hue.to_positive_degrees() as f64),
+                          ("red", srgb.red as f64),
+                          ("green", srgb.green as f64),
+                          ("blue", srgb.blue as f64)];
+    Value::Object(Rc::new(properties.into_iter()
+                                    .map(|(name, value)| (name.to_string(), Value::Float(value)))
+                                    .collect()))
+}
+
+/// What a script computed for a scheme.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptScore {
+    /// The value of the script.
+    pub value: f32,
+    pub named: Vec<(String, f32)>,
+}
+
+impl ScriptScore {
+    pub fn total(&self) -> f32 {
+        self.value + self.named.iter().fold(0.0, |sum, &(_, score)| sum + score)
+    }
+}
+
+struct Script {
+    path: String,
+    /// The parsed source, or why it doesn't parse.
+    program: Result<Program, String>,
+    /// When the file was last changed as of loading it.
+    modified: Option<SystemTime>,
+    /// The first error since it was last taken, so that a broken script
+    /// reports once per generation instead of once per evaluation.
+    error: Option<String>,
+    /// The free and fixed colors of the last scheme and its total score. The
+    /// fitness and the priority levels of a scheme both include the score,
+    /// so it is asked for twice in a row.
+    last: Option<(Vec<Lab>, Vec<Lab>, f32)>,
+}
+
+/// A script loaded from a file. Clones share the script, so reloading it
+/// changes the fitness of every problem description it was cloned into.
+#[derive(Clone)]
+pub struct FitnessScript {
+    script: Rc<RefCell<Script>>,
+}
+
+fn parse(source: &str) -> Result<Program, String> {
+    Program::parse(source).map_err(|err| format!("script error: {}", err))
+}
+
+/// Compares the colors by their fitness cache keys.
+fn same_colors(a: &[Lab], b: &[Lab]) -> bool {
+    a.len() == b.len() &&
+    a.iter().zip(b.iter()).all(|(a, b)| key(a) == key(b))
+}
+
+fn read(path: &str) -> io::Result<(String, Option<SystemTime>)> {
+    let mut source = String::new();
+    try!(File::open(path).and_then(|mut file| file.read_to_string(&mut source)));
+    let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    Ok((source, modified))
+}
+
+impl FitnessScript {
+    pub fn load(path: &str) -> Result<FitnessScript, Error> {
+        let (source, modified) = try!(read(path).map_err(|err| {
+            Error::io(format!("could not read script {}", path), err)
+        }));
+        Ok(FitnessScript::from_source(&source).with_path(path, modified))
+    }
+
+    pub fn from_source(source: &str) -> FitnessScript {
+        FitnessScript {
+            script: Rc::new(RefCell::new(Script {
+                path: String::new(),
+                program: parse(source),
+                modified: None,
+                error: None,
+                last: None,
+            })),
+        }
+    }
+
+    fn with_path(self, path: &str, modified: Option<SystemTime>) -> FitnessScript {
+        {
+            let mut script = self.script.borrow_mut();
+            script.path = path.to_string();
+            script.modified = modified;
+        }
+        self
+    }
+
+    /// Reads the file again if it changed since it was loaded. Returns
+    /// whether it was reloaded.
+    pub fn reload_if_changed(&self) -> io::Result<bool> {
+        let mut script = self.script.borrow_mut();
+        if script.path.is_empty() {
+            return Ok(false);
+        }
+        let modified = fs::metadata(&script.path).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_none() || modified == script.modified {
+            return Ok(false);
+        }
+        let (source, modified) = try!(read(&script.path));
+        script.program = parse(&source);
+        script.modified = modified;
+        script.error = None;
+        script.last = None;
+        Ok(true)
+    }
+
+    pub fn evaluate(&self,
+                    free_colors: &[Lab],
+                    fixed_colors: &[Lab])
+                    -> Result<ScriptScore, String> {
+        let colors = |colors: &[Lab]| {
+            Value::Array(Rc::new(colors.iter().map(color_value).collect()))
+        };
+        let variables = vec![("free".to_string(), colors(free_colors)),
+                             ("free_count".to_string(), Value::Int(free_colors.len() as i64)),
+                             ("fixed".to_string(), colors(fixed_colors)),
+                             ("fixed_count".to_string(), Value::Int(fixed_colors.len() as i64)),
+                             ("scores".to_string(), Value::Scores)];
+        let script = self.script.borrow();
+        let program = match script.program {
+            Ok(ref program) => program,
+            Err(ref err) => return Err(err.clone()),
+        };
+        let (value, named) = try!(program.run(variables)
+                                         .map_err(|err| format!("script error: {}", err)));
+        let value = try!(value.as_float().ok_or(format!("script error: the value of the script \
+                                                          is {}, not a number",
+                                                         value.type_name())));
+        Ok(ScriptScore {
+            value: value as f32,
+            named: named.into_iter().map(|(name, score)| (name, score as f32)).collect(),
+        })
+    }
+
+    /// The total score, or 0 if the script fails. The error is kept for
+    /// `take_error`. The script only runs again if the colors differ from
+    /// the last call.
+    pub fn score(&self, free_colors: &[Lab], fixed_colors: &[Lab]) -> f32 {
+        if let Some((ref free, ref fixed, score)) = self.script.borrow().last {
+            if same_colors(free, free_colors) && same_colors(fixed, fixed_colors) {
+                return score;
+            }
+        }
+        let score = match self.evaluate(free_colors, fixed_colors) {
+            Ok(score) => score.total(),
+            Err(err) => {
+                let mut script = self.script.borrow_mut();
+                if script.error.is_none() {
+                    script.error = Some(err);
+                }
+                0.0
+            }
+        };
+        self.script.borrow_mut().last = Some((free_colors.to_vec(), fixed_colors.to_vec(), score));
+        score
+    }
+
+    pub fn take_error(&self) -> Option<String> {
+        self.script.borrow_mut().error.take()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use palette::Lab;
+
+    #[test]
+    fn scores() {
+        let script = FitnessScript::from_source("scores.add(\"first\", free[0].l); \
+                                                 scores.add(\"fixed\", fixed[0].l); \
+                                                 free[1].l");
+        let free = vec![Lab::new(0.5, 0.0, 0.0), Lab::new(0.25, 0.1, 0.0)];
+        let score = script.evaluate(&free, &[Lab::new(0.75, 0.0, 0.0)]).unwrap();
+        assert_eq!(score.value, 25.0);
+        assert_eq!(score.named, vec![("first".to_string(), 50.0), ("fixed".to_string(), 75.0)]);
+        assert_eq!(score.total(), 150.0);
+    }
+
+    #[test]
+    fn errors_score_zero() {
+        let script = FitnessScript::from_source("free[0].unknown");
+        assert_eq!(script.score(&[Lab::new(0.5, 0.0, 0.0)], &[]), 0.0);
+        assert!(script.take_error().is_some());
+        assert_eq!(script.take_error(), None);
+    }
+
+    #[test]
+    fn endless_loops_fail() {
+        let script = FitnessScript::from_source("let i = 0; while true { i = i + 1; } 0.0");
+        assert_eq!(script.score(&[Lab::new(0.5, 0.0, 0.0)], &[]), 0.0);
+        assert!(script.take_error().is_some());
+    }
+}
@@ -0,0 +1,98 @@
+use palette::Lab;
+use rand::Rng;
+use std::fs::File;
+use std::io::Read;
+
+use color::euclidean_distance;
+use colorscheme::{ColorScheme, random_scheme};
+use error::Error;
+use fitness::ColorSchemeProblemDescription;
+
+/// All `#rrggbb` and `#rgb` colors in the text, in order. This reads hex
+/// lists as well as the CSS and JSON exports and most theme files.
+pub fn parse_hex_colors(text: &str) -> Vec<Lab> {
+    let bytes = text.as_bytes();
+    let mut colors: Vec<Lab> = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'#' {
+            i += 1;
+            continue;
+        }
+        let digits = bytes[i + 1..].iter().take_while(|b| (**b as char).is_digit(16)).count();
+        let hex = &text[i + 1..i + 1 + digits];
+        let channel = |s: &str| u8::from_str_radix(s, 16).unwrap();
+        match digits {
+            6 => {
+                colors.push(srgb!(channel(&hex[0..2]), channel(&hex[2..4]), channel(&hex[4..6])))
+            }
+            3 => {
+                let short = |s: &str| channel(s) * 17;
+                colors.push(srgb!(short(&hex[0..1]), short(&hex[1..2]), short(&hex[2..3])))
+            }
+            _ => {}
+        }
+        i += 1 + digits;
+    }
+    colors
+}
+
+pub fn read_palette(path: &str) -> Result<Vec<Lab>, Error> {
+    let mut text = String::new();
+    try!(File::open(path)
+             .and_then(|mut file| file.read_to_string(&mut text))
+             .map_err(|err| Error::io(format!("could not read palette {}", path), err)));
+    let colors = parse_hex_colors(&text);
+    if colors.is_empty() {
+        return Err(Error::parse(path, "no #rrggbb colors found"));
+    }
+    Ok(colors)
+}
+
+/// Turns a palette into a scheme for the problem. Colors within 1 ΔE of one
+/// of the fixed colors are skipped, so exported schemes can be read back.
+/// Missing free colors are random, surplus colors are ignored.
+pub fn scheme_from_palette<R: Rng>(palette: &[Lab],
+                                   descr: &ColorSchemeProblemDescription,
+                                   rng: &mut R)
+                                   -> ColorScheme {
+    let mut free: Vec<Lab> = palette.iter()
+                                    .filter(|c| {
+                                        descr.fixed_colors
+                                             .iter()
+                                             .all(|f| euclidean_distance(c, f) > 1.0)
+                                    })
+                                    .take(descr.free_color_count)
+                                    .cloned()
+                                    .collect();
+    let missing = descr.free_color_count - free.len();
+    free.extend(random_scheme(missing, rng).free_colors);
+    ColorScheme::new(free)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use export::hex;
+    use fitness::ColorSchemeProblemDescription;
+    use rand::{SeedableRng, StdRng};
+
+    #[test]
+    fn parse_theme() {
+        let css = ":root {\n  --fixed-0: #002b36;\n  --keyword: #F0A;\n  --x: #12345;\n}";
+        let colors: Vec<String> = parse_hex_colors(css).iter().map(hex).collect();
+        assert_eq!(colors, vec!["#002b36", "#ff00aa"]);
+    }
+
+    #[test]
+    fn skips_fixed_colors() {
+        let seed: &[usize] = &[1];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let descr = ColorSchemeProblemDescription::new(2, vec![srgb!(0, 43, 54)]);
+        // #002c37 is within 1 ΔE of the fixed color
+        let palette = parse_hex_colors("#002b36 #002c37 #dc322f #859900 #268bd2");
+        let scheme = scheme_from_palette(&palette, &descr, &mut rng);
+        let colors: Vec<String> = scheme.free_colors.iter().map(hex).collect();
+        assert_eq!(colors, vec!["#dc322f", "#859900"]);
+    }
+}
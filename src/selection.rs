@@ -0,0 +1,314 @@
+use rand::Rng;
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use genetic::{Individual, Selection, Tournament};
+
+/// Parent selection schemes. All of them expect the individuals sorted by
+/// `sort_by_fitness`. Tournaments compare by Deb's rules, so feasible
+/// individuals win, while `Roulette` and `StochasticUniversal` only look at
+/// the fitness.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SelectionStrategy {
+    /// A `Tournament` of the given size.
+    Tournament(usize),
+    /// Probability proportional to the fitness above the population's worst.
+    Roulette,
+    /// Linear ranking with a selection pressure between 1 (uniform) and 2.
+    Rank(f32),
+    /// Like `Roulette`, but all parents are drawn with one spin of evenly
+    /// spaced pointers, which reduces the sampling noise.
+    StochasticUniversal,
+    /// Uniformly from the given fraction of the best individuals.
+    Truncation(f32),
+}
+
+impl Default for SelectionStrategy {
+    fn default() -> SelectionStrategy {
+        SelectionStrategy::Tournament(4)
+    }
+}
+
+impl SelectionStrategy {
+    /// Parses `tournament[:size]`, `roulette`, `rank[:pressure]`, `sus` or
+    /// `truncation[:fraction]`.
+    pub fn from_name(name: &str) -> Result<SelectionStrategy, String> {
+        let mut parts = name.splitn(2, ':');
+        let kind = parts.next().unwrap_or("");
+        let parameter = parts.next();
+        let invalid = || format!("invalid parameter for selection {}", name);
+        let strategy = match kind {
+            "tournament" => {
+                let size = try!(parameter_or(parameter, 4, name));
+                if size < 1 {
+                    return Err(invalid());
+                }
+                SelectionStrategy::Tournament(size)
+            }
+            "roulette" => SelectionStrategy::Roulette,
+            "rank" => {
+                let pressure = try!(parameter_or(parameter, 1.5, name));
+                if pressure < 1.0 || pressure > 2.0 {
+                    return Err(invalid());
+                }
+                SelectionStrategy::Rank(pressure)
+            }
+            "sus" => SelectionStrategy::StochasticUniversal,
+            "truncation" => {
+                let fraction = try!(parameter_or(parameter, 0.5, name));
+                if fraction <= 0.0 || fraction > 1.0 {
+                    return Err(invalid());
+                }
+                SelectionStrategy::Truncation(fraction)
+            }
+            _ => {
+                return Err(format!("unknown selection {}, expected tournament, roulette, rank, \
+                                    sus or truncation",
+                                   name))
+            }
+        };
+        Ok(strategy)
+    }
+}
+
+fn parameter_or<T: FromStr>(parameter: Option<&str>,
+                            default: T,
+                            name: &str)
+                            -> Result<T, String> {
+    match parameter {
+        Some(p) => p.parse().map_err(|_| format!("invalid parameter for selection {}", name)),
+        None => Ok(default),
+    }
+}
+
+impl FromStr for SelectionStrategy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<SelectionStrategy, String> {
+        SelectionStrategy::from_name(name)
+    }
+}
+
+impl fmt::Display for SelectionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SelectionStrategy::Tournament(size) => write!(f, "tournament:{}", size),
+            SelectionStrategy::Roulette => write!(f, "roulette"),
+            SelectionStrategy::Rank(pressure) => write!(f, "rank:{}", pressure),
+            SelectionStrategy::StochasticUniversal => write!(f, "sus"),
+            SelectionStrategy::Truncation(fraction) => write!(f, "truncation:{}", fraction),
+        }
+    }
+}
+
+impl Selection for SelectionStrategy {
+    fn select<G, R: Rng>(&self, individuals: &[Individual<G>], count: usize, rng: &mut R)
+                         -> Vec<usize> {
+        let n = individuals.len();
+        assert!(n > 0);
+        match *self {
+            SelectionStrategy::Tournament(size) => {
+                Tournament { size: size }.select(individuals, count, rng)
+            }
+            SelectionStrategy::Roulette => {
+                let cumulative = cumulative(&windowed_fitness(individuals));
+                let total = cumulative[n - 1];
+                (0..count).map(|_| pick(&cumulative, rng.gen_range(0.0, total))).collect()
+            }
+            SelectionStrategy::Rank(pressure) => {
+                let cumulative = cumulative(&rank_weights(n, pressure as f64));
+                let total = cumulative[n - 1];
+                (0..count).map(|_| pick(&cumulative, rng.gen_range(0.0, total))).collect()
+            }
+            SelectionStrategy::StochasticUniversal => {
+                if count == 0 {
+                    return vec![];
+                }
+                let cumulative = cumulative(&windowed_fitness(individuals));
+                let spacing = cumulative[n - 1] / count as f64;
+                let start = rng.gen_range(0.0, spacing);
+                let mut selected: Vec<usize> = (0..count)
+                                                   .map(|i| {
+                                                       pick(&cumulative, start + i as f64 * spacing)
+                                                   })
+                                                   .collect();
+                // the pointers select in fitness order, but mates should be random
+                rng.shuffle(&mut selected);
+                selected
+            }
+            SelectionStrategy::Truncation(fraction) => {
+                let best = ((fraction * n as f32).ceil() as usize).max(1).min(n);
+                (0..count).map(|_| rng.gen_range(0, best)).collect()
+            }
+        }
+    }
+}
+
+/// Fitness shifted so that the worst individual has a weight of zero. An
+/// undefined (NaN) fitness gets a weight of zero as well. If all individuals
+/// are equally fit, or an infinite fitness makes a weight or the total
+/// infinite, all get the same weight.
+fn windowed_fitness<G>(individuals: &[Individual<G>]) -> Vec<f64> {
+    // min ignores NaN
+    let worst = individuals.iter().fold(::std::f32::MAX, |min, i| min.min(i.fitness()));
+    let weights: Vec<f64> = individuals.iter()
+                                       .map(|i| {
+                                           let weight = (i.fitness() - worst) as f64;
+                                           if weight.is_nan() {
+                                               0.0
+                                           } else {
+                                               weight
+                                           }
+                                       })
+                                       .collect();
+    let total = weights.iter().fold(0.0, |sum, &w| sum + w);
+    if weights.iter().all(|&w| w <= 0.0) || !total.is_finite() {
+        vec![1.0; individuals.len()]
+    } else {
+        weights
+    }
+}
+
+/// Linear ranking: the best gets `pressure`, the worst `2 - pressure`.
+fn rank_weights(n: usize, pressure: f64) -> Vec<f64> {
+    (0..n)
+        .map(|rank| {
+            let scaled = if n > 1 {
+                (n - 1 - rank) as f64 / (n - 1) as f64
+            } else {
+                1.0
+            };
+            (2.0 - pressure) + 2.0 * (pressure - 1.0) * scaled
+        })
+        .collect()
+}
+
+fn cumulative(weights: &[f64]) -> Vec<f64> {
+    weights.iter()
+           .scan(0.0, |sum, &w| {
+               *sum += w;
+               Some(*sum)
+           })
+           .collect()
+}
+
+/// Index of the first cumulative weight above `x`.
+fn pick(cumulative: &[f64], x: f64) -> usize {
+    let last = cumulative.len() - 1;
+    // a NaN weight, from a NaN fitness, compares as smaller instead of panicking
+    match cumulative.binary_search_by(|c| c.partial_cmp(&x).unwrap_or(Ordering::Less)) {
+        Ok(i) => (i + 1).min(last),
+        Err(i) => i.min(last),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use genetic::{Individual, Selection};
+    use rand::{SeedableRng, StdRng};
+
+    /// 100 individuals with fitness 99, 98, ..., 0
+    fn population() -> Vec<Individual<()>> {
+        (0..100).rev().map(|f| Individual::evaluated((), f as f32)).collect()
+    }
+
+    fn mean_selected_fitness(strategy: SelectionStrategy) -> f32 {
+        let seed: &[usize] = &[42];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let population = population();
+        let selected = strategy.select(&population, 10000, &mut rng);
+        assert_eq!(selected.len(), 10000);
+        selected.iter().map(|&i| population[i].fitness()).sum::<f32>() / selected.len() as f32
+    }
+
+    #[test]
+    fn selection_pressure() {
+        // uniform selection has a mean of 49.5
+        let uniform = mean_selected_fitness(SelectionStrategy::Tournament(1));
+        let roulette = mean_selected_fitness(SelectionStrategy::Roulette);
+        let sus = mean_selected_fitness(SelectionStrategy::StochasticUniversal);
+        let rank = mean_selected_fitness(SelectionStrategy::Rank(2.0));
+        let tournament = mean_selected_fitness(SelectionStrategy::Tournament(4));
+        let truncation = mean_selected_fitness(SelectionStrategy::Truncation(0.1));
+
+        assert!((uniform - 49.5).abs() < 2.0);
+        // weights proportional to fitness: E[f] = sum(f^2) / sum(f) = 66.33
+        assert!((roulette - 66.33).abs() < 2.0);
+        assert!((sus - 66.33).abs() < 0.1);
+        // rank 2.0 on a linear fitness distribution equals roulette
+        assert!((rank - 66.33).abs() < 2.0);
+        // expected maximum of 4 uniform draws: 99 - sum((k/100)^4) = 79.5
+        assert!((tournament - 79.5).abs() < 2.0);
+        assert!((truncation - 94.5).abs() < 1.0);
+    }
+
+    #[test]
+    fn rank_pressure_one_is_uniform() {
+        assert!((mean_selected_fitness(SelectionStrategy::Rank(1.0)) - 49.5).abs() < 2.0);
+    }
+
+    #[test]
+    fn truncation_selects_only_the_best() {
+        let seed: &[usize] = &[1];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let selected = SelectionStrategy::Truncation(0.25).select(&population(), 1000, &mut rng);
+        assert!(selected.iter().all(|&i| i < 25));
+    }
+
+    #[test]
+    fn equal_fitness_is_uniform() {
+        let seed: &[usize] = &[7];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let population: Vec<Individual<()>> = (0..10).map(|_| Individual::evaluated((), 3.0))
+                                                      .collect();
+        let selected = SelectionStrategy::StochasticUniversal.select(&population, 10, &mut rng);
+        let mut sorted = selected.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..10).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn nan_fitness_is_never_selected() {
+        let seed: &[usize] = &[3];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let population: Vec<Individual<()>> = vec![3.0, 2.0, 1.0, ::std::f32::NAN]
+                                                  .into_iter()
+                                                  .map(|f| Individual::evaluated((), f))
+                                                  .collect();
+        for &strategy in [SelectionStrategy::Roulette, SelectionStrategy::StochasticUniversal]
+                             .iter() {
+            let selected = strategy.select(&population, 100, &mut rng);
+            assert!(selected.iter().all(|&i| i < 2), "{}: {:?}", strategy, selected);
+        }
+    }
+
+    #[test]
+    fn infinite_fitness_does_not_panic() {
+        let seed: &[usize] = &[5];
+        let mut rng: StdRng = SeedableRng::from_seed(seed);
+        let population: Vec<Individual<()>> = vec![::std::f32::INFINITY,
+                                                   1.0,
+                                                   ::std::f32::NAN,
+                                                   ::std::f32::NEG_INFINITY]
+                                                  .into_iter()
+                                                  .map(|f| Individual::evaluated((), f))
+                                                  .collect();
+        for &strategy in [SelectionStrategy::Roulette, SelectionStrategy::StochasticUniversal]
+                             .iter() {
+            let selected = strategy.select(&population, 100, &mut rng);
+            assert_eq!(selected.len(), 100);
+            assert!(selected.iter().all(|&i| i < population.len()), "{}: {:?}", strategy, selected);
+        }
+    }
+
+    #[test]
+    fn parse_selection() {
+        assert_eq!(SelectionStrategy::from_name("tournament:2"),
+                   Ok(SelectionStrategy::Tournament(2)));
+        assert_eq!(SelectionStrategy::from_name("rank"), Ok(SelectionStrategy::Rank(1.5)));
+        assert!(SelectionStrategy::from_name("rank:3").is_err());
+        assert!(SelectionStrategy::from_name("lottery").is_err());
+    }
+}